@@ -0,0 +1,554 @@
+use k8s_openapi::ByteString;
+use k8s_openapi::api::admissionregistration::v1::{
+    MutatingWebhook, MutatingWebhookConfiguration, RuleWithOperations, ServiceReference,
+    ValidatingWebhook, ValidatingWebhookConfiguration, WebhookClientConfig,
+};
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, LabelSelectorRequirement};
+use kube::api::{ObjectMeta, Patch, PatchParams, PostParams};
+use kube::{Api, Client};
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    KeyUsagePurpose,
+};
+use std::error::Error;
+use std::path::Path;
+use time::{Duration, OffsetDateTime};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::config::{BootstrapConfig, TlsConfig};
+use crate::crd::FIELD_MANAGER;
+
+type BootstrapResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+const CA_CERT: &str = "ca.crt";
+const CA_KEY: &str = "ca.key";
+const TLS_CERT: &str = "tls.crt";
+const TLS_KEY: &str = "tls.key";
+
+const CA_VALIDITY_DAYS: i64 = 3650;
+const SECRET_UPDATE_ATTEMPTS: usize = 3;
+
+/// Certificates as stored in the Secret. `ca_bundle` lists the current CA
+/// first, followed by the previous one while certificates it signed may
+/// still be served by other replicas.
+#[derive(Clone)]
+struct Material {
+    ca_bundle: String,
+    ca_key: String,
+    tls_cert: String,
+    tls_key: String,
+}
+
+/// Makes sure the Secret holds a CA and a serving certificate valid for the
+/// webhook Service, writes the serving pair to the configured TLS paths and
+/// registers the webhook configurations trusting the CA, limited to the
+/// `watched` namespaces when any are listed. Safe to call from every replica:
+/// the Secret is the single source of truth.
+pub async fn ensure(
+    client: &Client,
+    config: &BootstrapConfig,
+    tls: &TlsConfig,
+    watched: &[String],
+) -> BootstrapResult<()> {
+    let material = ensure_secret(client, config).await?;
+    if let (Some(cert), Some(key)) = (&tls.cert_path, &tls.key_path) {
+        // Key first: the reloader retries until both halves match.
+        write_if_changed(key, &material.tls_key)?;
+        write_if_changed(cert, &material.tls_cert)?;
+    }
+    register_webhooks(client, config, &material.ca_bundle, watched).await
+}
+
+/// Re-runs [`ensure`] every check interval until `shutdown` is cancelled, so
+/// the serving certificate is replaced before it expires.
+pub async fn maintain(
+    client: Client,
+    config: BootstrapConfig,
+    tls: TlsConfig,
+    watched: Vec<String>,
+    shutdown: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(config.check_interval()) => {}
+            _ = shutdown.cancelled() => return,
+        }
+        if let Err(e) = ensure(&client, &config, &tls, &watched).await {
+            warn!("Cannot refresh webhook certificate: {e}");
+        }
+    }
+}
+
+async fn ensure_secret(client: &Client, config: &BootstrapConfig) -> BootstrapResult<Material> {
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), &config.namespace);
+    let name = &config.secret_name;
+
+    for _ in 0..SECRET_UPDATE_ATTEMPTS {
+        let existing = secrets.get_opt(name).await?;
+        let current = existing.as_ref().and_then(Material::from_secret);
+        let material = match &current {
+            Some(current) if serving_cert_is_current(config, current) => return Ok(current.clone()),
+            _ => issue(config, current.as_ref())?,
+        };
+
+        let secret = material.to_secret(config, existing.as_ref());
+        let result = match existing {
+            Some(_) => secrets.replace(name, &PostParams::default(), &secret).await,
+            None => secrets.create(&PostParams::default(), &secret).await,
+        };
+        match result {
+            Ok(_) => {
+                info!(
+                    "Issued webhook certificate into Secret {}/{name}",
+                    config.namespace
+                );
+                return Ok(material);
+            }
+            // Another replica issued first; start over from what it wrote.
+            Err(kube::Error::Api(e)) if e.code == 409 => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(format!(
+        "Secret {}/{name} kept changing while issuing the webhook certificate",
+        config.namespace
+    )
+    .into())
+}
+
+/// The serving certificate is kept while it covers every Service name and is
+/// not inside the rotation window.
+fn serving_cert_is_current(config: &BootstrapConfig, material: &Material) -> bool {
+    let rotate_at = OffsetDateTime::now_utc() + days(config.rotate_before_days);
+    match inspect(&material.tls_cert) {
+        Ok((not_after, names)) => {
+            not_after > rotate_at && service_names(config).iter().all(|n| names.contains(n))
+        }
+        Err(e) => {
+            warn!("Stored webhook certificate is unreadable, issuing a new one: {e}");
+            false
+        }
+    }
+}
+
+/// Signs a new serving certificate, reusing the CA unless it would expire
+/// before the new certificate does.
+fn issue(config: &BootstrapConfig, current: Option<&Material>) -> BootstrapResult<Material> {
+    let now = OffsetDateTime::now_utc();
+    let not_after = now + days(config.validity_days);
+
+    let reusable_ca = current.and_then(|current| {
+        let (ca_not_after, _) = inspect(&current.ca_bundle).ok()?;
+        (ca_not_after > not_after).then_some(current)
+    });
+    let (ca_bundle, ca_key) = match reusable_ca {
+        Some(current) => (
+            current.ca_bundle.clone(),
+            KeyPair::from_pem(&current.ca_key)?,
+        ),
+        None => {
+            let key = KeyPair::generate()?;
+            let mut params = CertificateParams::default();
+            params
+                .distinguished_name
+                .push(DnType::CommonName, "kube-guardian webhook CA");
+            params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+            params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+            params.not_before = now - Duration::hours(1);
+            params.not_after = now + Duration::days(CA_VALIDITY_DAYS);
+            let ca = params.self_signed(&key)?;
+            info!("Generated a new webhook CA");
+
+            // Keep trusting the outgoing CA until every replica serves the new certificate.
+            let mut bundle = ca.pem();
+            if let Some(previous) = current.and_then(|c| first_pem(&c.ca_bundle)) {
+                bundle.push_str(&previous);
+            }
+            (bundle, key)
+        }
+    };
+
+    // Rebuilding the CA certificate from its PEM yields the same issuer name
+    // and key identifier, which is all signing needs.
+    let issuer = CertificateParams::from_ca_cert_pem(&ca_bundle)?.self_signed(&ca_key)?;
+
+    let key = KeyPair::generate()?;
+    let mut params = CertificateParams::new(service_names(config))?;
+    params.distinguished_name.push(
+        DnType::CommonName,
+        format!("{}.{}.svc", config.service_name, config.namespace),
+    );
+    params.key_usages = vec![
+        KeyUsagePurpose::DigitalSignature,
+        KeyUsagePurpose::KeyEncipherment,
+    ];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    params.not_before = now - Duration::hours(1);
+    params.not_after = not_after;
+    let cert = params.signed_by(&key, &issuer, &ca_key)?;
+
+    Ok(Material {
+        ca_bundle,
+        ca_key: ca_key.serialize_pem(),
+        tls_cert: cert.pem(),
+        tls_key: key.serialize_pem(),
+    })
+}
+
+/// DNS names the API server may use to reach the webhook Service.
+fn service_names(config: &BootstrapConfig) -> Vec<String> {
+    let (svc, ns) = (&config.service_name, &config.namespace);
+    vec![
+        svc.clone(),
+        format!("{svc}.{ns}"),
+        format!("{svc}.{ns}.svc"),
+        format!("{svc}.{ns}.svc.cluster.local"),
+    ]
+}
+
+/// Expiry and DNS subject alternative names of the first certificate in `pem`.
+fn inspect(pem: &str) -> BootstrapResult<(OffsetDateTime, Vec<String>)> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes())
+        .map_err(|e| format!("invalid PEM: {e}"))?;
+    let cert = pem
+        .parse_x509()
+        .map_err(|e| format!("invalid certificate: {e}"))?;
+    let not_after = cert.validity().not_after.to_datetime();
+    let names = match cert.subject_alternative_name()? {
+        Some(san) => san
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                x509_parser::extensions::GeneralName::DNSName(dns) => Some(dns.to_string()),
+                _ => None,
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    Ok((not_after, names))
+}
+
+fn first_pem(bundle: &str) -> Option<String> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let end = bundle.find(END)? + END.len();
+    Some(format!("{}\n", bundle[..end].trim_start()))
+}
+
+fn days(days: u32) -> Duration {
+    Duration::days(i64::from(days))
+}
+
+impl Material {
+    fn from_secret(secret: &Secret) -> Option<Material> {
+        let data = secret.data.as_ref()?;
+        let field = |key: &str| {
+            data.get(key)
+                .and_then(|value| String::from_utf8(value.0.clone()).ok())
+        };
+        Some(Material {
+            ca_bundle: field(CA_CERT)?,
+            ca_key: field(CA_KEY)?,
+            tls_cert: field(TLS_CERT)?,
+            tls_key: field(TLS_KEY)?,
+        })
+    }
+
+    fn to_secret(&self, config: &BootstrapConfig, existing: Option<&Secret>) -> Secret {
+        let data = [
+            (CA_CERT, &self.ca_bundle),
+            (CA_KEY, &self.ca_key),
+            (TLS_CERT, &self.tls_cert),
+            (TLS_KEY, &self.tls_key),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), ByteString(value.clone().into_bytes())))
+        .collect();
+
+        Secret {
+            metadata: ObjectMeta {
+                name: Some(config.secret_name.clone()),
+                namespace: Some(config.namespace.clone()),
+                // Replacing with the version we read turns a concurrent write into a 409.
+                resource_version: existing.and_then(|s| s.metadata.resource_version.clone()),
+                labels: existing.and_then(|s| s.metadata.labels.clone()),
+                annotations: existing.and_then(|s| s.metadata.annotations.clone()),
+                ..ObjectMeta::default()
+            },
+            type_: Some("kubernetes.io/tls".to_string()),
+            data: Some(data),
+            ..Secret::default()
+        }
+    }
+}
+
+/// Replaces `path` through a rename so the reloader never reads a partial file.
+fn write_if_changed(path: &Path, contents: &str) -> std::io::Result<()> {
+    if std::fs::read_to_string(path).is_ok_and(|current| current == contents) {
+        return Ok(());
+    }
+    let mut staging = path.as_os_str().to_owned();
+    staging.push(".tmp");
+    std::fs::write(&staging, contents)?;
+    std::fs::rename(&staging, path)
+}
+
+async fn register_webhooks(
+    client: &Client,
+    config: &BootstrapConfig,
+    ca_bundle: &str,
+    watched: &[String],
+) -> BootstrapResult<()> {
+    let name = &config.webhook_configuration_name;
+    let params = PatchParams::apply(FIELD_MANAGER).force();
+    let metadata = ObjectMeta {
+        name: Some(name.clone()),
+        ..ObjectMeta::default()
+    };
+
+    let validating = ValidatingWebhookConfiguration {
+        metadata: metadata.clone(),
+        webhooks: Some(vec![ValidatingWebhook {
+            name: "validate.guardian.io".to_string(),
+            client_config: client_config(config, ca_bundle, "/validate"),
+            rules: Some(admission_rules()),
+            failure_policy: Some(config.failure_policy.as_str().to_string()),
+            side_effects: "None".to_string(),
+            admission_review_versions: vec!["v1".to_string()],
+            namespace_selector: Some(namespace_selector(config, watched)),
+            timeout_seconds: Some(10),
+            ..ValidatingWebhook::default()
+        }]),
+    };
+    Api::<ValidatingWebhookConfiguration>::all(client.clone())
+        .patch(name, &params, &Patch::Apply(&validating))
+        .await?;
+
+    let mutating = MutatingWebhookConfiguration {
+        metadata,
+        webhooks: Some(vec![MutatingWebhook {
+            name: "mutate.guardian.io".to_string(),
+            client_config: client_config(config, ca_bundle, "/mutate"),
+            rules: Some(admission_rules()),
+            failure_policy: Some(config.failure_policy.as_str().to_string()),
+            side_effects: "None".to_string(),
+            admission_review_versions: vec!["v1".to_string()],
+            namespace_selector: Some(namespace_selector(config, watched)),
+            timeout_seconds: Some(10),
+            ..MutatingWebhook::default()
+        }]),
+    };
+    Api::<MutatingWebhookConfiguration>::all(client.clone())
+        .patch(name, &params, &Patch::Apply(&mutating))
+        .await?;
+    Ok(())
+}
+
+fn client_config(config: &BootstrapConfig, ca_bundle: &str, path: &str) -> WebhookClientConfig {
+    WebhookClientConfig {
+        ca_bundle: Some(ByteString(ca_bundle.as_bytes().to_vec())),
+        service: Some(ServiceReference {
+            name: config.service_name.clone(),
+            namespace: config.namespace.clone(),
+            path: Some(path.to_string()),
+            port: Some(config.service_port),
+        }),
+        url: None,
+    }
+}
+
+fn admission_rules() -> Vec<RuleWithOperations> {
+    let any = || Some(vec!["*".to_string()]);
+    vec![RuleWithOperations {
+        api_groups: any(),
+        api_versions: any(),
+        operations: Some(vec!["CREATE".to_string(), "UPDATE".to_string()]),
+        resources: any(),
+        scope: Some("*".to_string()),
+    }]
+}
+
+// Reviewing our own namespace could lock the webhook out of restarting.
+// Cluster-scoped resources are sent regardless of the selector.
+fn namespace_selector(config: &BootstrapConfig, watched: &[String]) -> LabelSelector {
+    let mut expressions = vec![LabelSelectorRequirement {
+        key: "kubernetes.io/metadata.name".to_string(),
+        operator: "NotIn".to_string(),
+        values: Some(vec![config.namespace.clone(), "kube-system".to_string()]),
+    }];
+    if !watched.is_empty() {
+        expressions.push(LabelSelectorRequirement {
+            key: "kubernetes.io/metadata.name".to_string(),
+            operator: "In".to_string(),
+            values: Some(watched.to_vec()),
+        });
+    }
+    LabelSelector {
+        match_expressions: Some(expressions),
+        match_labels: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BootstrapConfig {
+        BootstrapConfig::default()
+    }
+
+    /// The DER contents of every certificate in `bundle`.
+    fn pems(bundle: &str) -> Vec<Vec<u8>> {
+        x509_parser::pem::Pem::iter_from_buffer(bundle.as_bytes())
+            .map(|pem| pem.unwrap().contents)
+            .collect()
+    }
+
+    fn issuer_and_subject(pem: &str) -> (String, String) {
+        let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes()).unwrap();
+        let cert = pem.parse_x509().unwrap();
+        (cert.issuer().to_string(), cert.subject().to_string())
+    }
+
+    /// Material whose CA expires in `ca_days`.
+    fn with_ca_expiring_in(ca_days: i64) -> Material {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::default();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "outgoing CA");
+        params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+        params.not_after = OffsetDateTime::now_utc() + Duration::days(ca_days);
+        let ca = params.self_signed(&key).unwrap();
+        Material {
+            ca_bundle: ca.pem(),
+            ca_key: key.serialize_pem(),
+            tls_cert: String::new(),
+            tls_key: String::new(),
+        }
+    }
+
+    #[test]
+    fn fresh_issue_covers_every_service_name() {
+        let material = issue(&config(), None).unwrap();
+        assert_eq!(pems(&material.ca_bundle).len(), 1);
+
+        let (not_after, names) = inspect(&material.tls_cert).unwrap();
+        assert_eq!(names, service_names(&config()));
+        assert!(names.contains(&"kube-guardian-webhook.kube-guardian.svc".to_string()));
+        let expected = OffsetDateTime::now_utc() + Duration::days(90);
+        assert!((not_after - expected).abs() < Duration::minutes(5));
+
+        let (issuer, _) = issuer_and_subject(&material.tls_cert);
+        let (_, ca_subject) = issuer_and_subject(&material.ca_bundle);
+        assert_eq!(issuer, ca_subject);
+        assert!(KeyPair::from_pem(&material.tls_key).is_ok());
+    }
+
+    #[test]
+    fn fresh_certificates_are_current() {
+        let material = issue(&config(), None).unwrap();
+        assert!(serving_cert_is_current(&config(), &material));
+    }
+
+    #[test]
+    fn certificates_inside_the_rotation_window_are_replaced() {
+        let mut short = config();
+        short.validity_days = 20;
+        let material = issue(&short, None).unwrap();
+        // Expires in 20 days, rotated 30 days ahead.
+        assert!(!serving_cert_is_current(&config(), &material));
+    }
+
+    #[test]
+    fn certificates_missing_a_service_name_are_replaced() {
+        let material = issue(&config(), None).unwrap();
+        let mut moved = config();
+        moved.namespace = "security".to_string();
+        assert!(!serving_cert_is_current(&moved, &material));
+        let mut renamed = config();
+        renamed.service_name = "guardian".to_string();
+        assert!(!serving_cert_is_current(&renamed, &material));
+    }
+
+    #[test]
+    fn unreadable_certificates_are_replaced() {
+        let mut material = issue(&config(), None).unwrap();
+        material.tls_cert = "not a certificate".to_string();
+        assert!(!serving_cert_is_current(&config(), &material));
+    }
+
+    #[test]
+    fn reissuing_keeps_a_long_lived_ca() {
+        let first = issue(&config(), None).unwrap();
+        let second = issue(&config(), Some(&first)).unwrap();
+        assert_eq!(second.ca_bundle, first.ca_bundle);
+        assert_eq!(second.ca_key, first.ca_key);
+        assert_ne!(second.tls_cert, first.tls_cert);
+        let (issuer, _) = issuer_and_subject(&second.tls_cert);
+        assert_eq!(issuer, issuer_and_subject(&first.tls_cert).0);
+    }
+
+    #[test]
+    fn expiring_ca_is_replaced_and_kept_in_the_bundle() {
+        // The CA would expire before a new 90-day certificate.
+        let outgoing = with_ca_expiring_in(30);
+        let material = issue(&config(), Some(&outgoing)).unwrap();
+
+        let bundle = pems(&material.ca_bundle);
+        assert_eq!(bundle.len(), 2);
+        assert_eq!(bundle[1], pems(&outgoing.ca_bundle)[0]);
+        assert_ne!(material.ca_key, outgoing.ca_key);
+
+        let (issuer, _) = issuer_and_subject(&material.tls_cert);
+        assert_eq!(issuer, "CN=kube-guardian webhook CA");
+        let (ca_not_after, _) = inspect(&material.ca_bundle).unwrap();
+        assert!(ca_not_after > OffsetDateTime::now_utc() + Duration::days(3000));
+
+        // Only the previous CA is kept on the next rotation, not every one before it.
+        let next = issue(&config(), Some(&with_ca_bundle(&material, 30))).unwrap();
+        assert_eq!(pems(&next.ca_bundle).len(), 2);
+    }
+
+    /// `material` with its current CA replaced by one expiring in `ca_days`,
+    /// keeping the rest of the bundle.
+    fn with_ca_bundle(material: &Material, ca_days: i64) -> Material {
+        let expiring = with_ca_expiring_in(ca_days);
+        Material {
+            ca_bundle: format!("{}{}", expiring.ca_bundle, material.ca_bundle),
+            ..expiring
+        }
+    }
+
+    #[test]
+    fn first_pem_takes_the_current_ca() {
+        let first = issue(&config(), None).unwrap().ca_bundle;
+        let second = issue(&config(), None).unwrap().ca_bundle;
+        let bundle = format!("{first}{second}");
+        assert_eq!(pems(&first_pem(&bundle).unwrap()), pems(&first));
+        assert_eq!(first_pem("no certificates"), None);
+    }
+
+    #[test]
+    fn namespace_selector_skips_own_namespace_and_narrows_to_watched() {
+        let selector = namespace_selector(&config(), &[]);
+        let expressions = selector.match_expressions.unwrap();
+        assert_eq!(expressions.len(), 1);
+        assert_eq!(expressions[0].operator, "NotIn");
+        assert_eq!(
+            expressions[0].values,
+            Some(vec!["kube-guardian".to_string(), "kube-system".to_string()])
+        );
+
+        let watched = vec!["team-a".to_string(), "team-b".to_string()];
+        let expressions = namespace_selector(&config(), &watched)
+            .match_expressions
+            .unwrap();
+        assert_eq!(expressions.len(), 2);
+        assert_eq!(expressions[1].key, "kubernetes.io/metadata.name");
+        assert_eq!(expressions[1].operator, "In");
+        assert_eq!(expressions[1].values, Some(watched));
+    }
+}
@@ -1,7 +1,106 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
+#[command(
+    name = "kube-guardian",
+    version,
+    about = "Kubernetes governance operator"
+)]
 pub struct Cli {
-    #[arg(long)]
-    pub config: String,
+    /// Path to a YAML or TOML configuration file
+    #[arg(long, global = true, env = "KUBE_GUARDIAN_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub overrides: Overrides,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// Settings that take precedence over the configuration file. Each flag can
+/// also be supplied through its environment variable.
+#[derive(Args, Debug, Default)]
+pub struct Overrides {
+    /// Log level or tracing filter directive, e.g. `info` or `info,kube=warn`
+    #[arg(long, global = true, env = "KUBE_GUARDIAN_LOG_LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Namespaces to govern (comma separated); all namespaces when unset
+    #[arg(
+        long = "namespace",
+        global = true,
+        env = "KUBE_GUARDIAN_WATCH_NAMESPACES",
+        value_delimiter = ','
+    )]
+    pub watch_namespaces: Option<Vec<String>>,
+
+    /// Address the admission webhook listens on
+    #[arg(long, global = true, env = "KUBE_GUARDIAN_WEBHOOK_ADDR")]
+    pub webhook_addr: Option<SocketAddr>,
+
+    /// PEM certificate served by the admission webhook
+    #[arg(long, global = true, env = "KUBE_GUARDIAN_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key matching --tls-cert
+    #[arg(long, global = true, env = "KUBE_GUARDIAN_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Address the Prometheus metrics endpoint listens on
+    #[arg(long, global = true, env = "KUBE_GUARDIAN_METRICS_ADDR")]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Holder identity for leader election; defaults to the pod name
+    #[arg(long, global = true, env = "KUBE_GUARDIAN_LEADER_IDENTITY")]
+    pub leader_identity: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the controller and the admission webhook together
+    Run,
+    /// Run only the admission webhook server
+    Webhook,
+    /// Run only the GuardianPolicy controller
+    Controller,
+    /// Evaluate manifests against GuardianPolicy files without a cluster
+    Evaluate {
+        /// File containing one or more GuardianPolicy documents
+        #[arg(long, short, required = true)]
+        policy: Vec<PathBuf>,
+        /// Manifests to evaluate
+        #[arg(required = true)]
+        resources: Vec<PathBuf>,
+    },
+    /// Manage the CustomResourceDefinitions owned by kube-guardian
+    Crd {
+        #[command(subcommand)]
+        action: CrdCommand,
+    },
+    /// Print version information
+    Version,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CrdCommand {
+    /// Print the generated CRDs
+    Print {
+        #[arg(long, short, value_enum, default_value_t = OutputFormat::Yaml)]
+        output: OutputFormat,
+    },
+    /// Server-side apply the CRDs and wait until they are Established
+    Install {
+        /// Seconds to wait for each CRD to become Established
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum OutputFormat {
+    Yaml,
+    Json,
 }
@@ -0,0 +1,729 @@
+use serde::Deserialize;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
+
+use crate::cli::Overrides;
+use crate::crd::FailurePolicy;
+
+/// Operator configuration. Values are layered as defaults, then the
+/// configuration file, then environment variables and command-line flags.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct Config {
+    pub log_level: String,
+    /// Namespaces to govern. Empty means every namespace.
+    pub watch_namespaces: Vec<String>,
+    pub webhook: WebhookConfig,
+    pub controller: ControllerConfig,
+    pub leader_election: LeaderElectionConfig,
+    pub metrics: MetricsConfig,
+    pub shutdown: ShutdownConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub bind_address: SocketAddr,
+    pub tls: TlsConfig,
+    pub bootstrap: BootstrapConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert_path: Option<PathBuf>,
+    pub key_path: Option<PathBuf>,
+    /// Seconds between checks of the certificate files for rotation.
+    pub reload_interval_secs: u64,
+}
+
+/// Self-managed webhook certificates. When enabled, kube-guardian keeps a CA
+/// and serving certificate in a Secret, writes them to the TLS paths and
+/// registers its webhook configurations with the matching `caBundle`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct BootstrapConfig {
+    pub enabled: bool,
+    /// Namespace of the webhook Service and certificate Secret.
+    pub namespace: String,
+    pub service_name: String,
+    pub service_port: i32,
+    pub secret_name: String,
+    /// Name of both the Validating- and MutatingWebhookConfiguration.
+    pub webhook_configuration_name: String,
+    /// What the API server does when the webhook cannot be reached.
+    pub failure_policy: FailurePolicy,
+    /// Lifetime of the serving certificate in days.
+    pub validity_days: u32,
+    /// Issue a new serving certificate this many days before expiry.
+    pub rotate_before_days: u32,
+    /// Seconds between certificate expiry checks.
+    pub check_interval_secs: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct ControllerConfig {
+    /// Seconds between reconciles of a healthy policy.
+    pub requeue_interval_secs: u64,
+    /// Upper bound in seconds for the backoff between retries of a policy
+    /// whose reconcile failed.
+    pub error_requeue_interval_secs: u64,
+    /// Seconds between background scans of existing resources. Policy
+    /// changes also trigger a scan.
+    pub audit_interval_secs: u64,
+}
+
+/// Only the replica holding the Lease runs the reconciler and the background
+/// scanner; every replica serves the admission webhook.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct LeaderElectionConfig {
+    pub enabled: bool,
+    pub lease_name: String,
+    pub namespace: String,
+    /// Holder identity written to the Lease. Defaults to `$POD_NAME`, then
+    /// `$HOSTNAME`.
+    pub identity: Option<String>,
+    /// Seconds other replicas wait after the last renewal before taking over.
+    pub lease_duration_secs: u64,
+    /// Seconds the leader keeps trying to renew before it steps down.
+    pub renew_deadline_secs: u64,
+    /// Seconds between attempts to acquire or renew the Lease.
+    pub retry_period_secs: u64,
+}
+
+/// Prometheus metrics, served over plain HTTP at `/metrics` by every replica.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub bind_address: SocketAddr,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// Seconds in-flight reconciles and admission requests get to finish
+    /// after SIGTERM/SIGINT. Keep it below the pod's termination grace period.
+    pub drain_timeout_secs: u64,
+}
+
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, String),
+    Invalid(Vec<String>),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            log_level: "info".to_string(),
+            watch_namespaces: Vec::new(),
+            webhook: WebhookConfig::default(),
+            controller: ControllerConfig::default(),
+            leader_election: LeaderElectionConfig::default(),
+            metrics: MetricsConfig::default(),
+            shutdown: ShutdownConfig::default(),
+        }
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            bind_address: SocketAddr::from(([0, 0, 0, 0], 8443)),
+            tls: TlsConfig::default(),
+            bootstrap: BootstrapConfig::default(),
+        }
+    }
+}
+
+impl Default for BootstrapConfig {
+    fn default() -> Self {
+        BootstrapConfig {
+            enabled: false,
+            namespace: "kube-guardian".to_string(),
+            service_name: "kube-guardian-webhook".to_string(),
+            service_port: 443,
+            secret_name: "kube-guardian-webhook-tls".to_string(),
+            webhook_configuration_name: "kube-guardian".to_string(),
+            failure_policy: FailurePolicy::Ignore,
+            validity_days: 90,
+            rotate_before_days: 30,
+            check_interval_secs: 3600,
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            cert_path: None,
+            key_path: None,
+            reload_interval_secs: 10,
+        }
+    }
+}
+
+impl Default for ControllerConfig {
+    fn default() -> Self {
+        ControllerConfig {
+            requeue_interval_secs: 300,
+            error_requeue_interval_secs: 60,
+            audit_interval_secs: 600,
+        }
+    }
+}
+
+impl Default for LeaderElectionConfig {
+    fn default() -> Self {
+        LeaderElectionConfig {
+            enabled: true,
+            lease_name: "kube-guardian".to_string(),
+            namespace: "kube-guardian".to_string(),
+            identity: None,
+            lease_duration_secs: 15,
+            renew_deadline_secs: 10,
+            retry_period_secs: 2,
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            enabled: true,
+            bind_address: SocketAddr::from(([0, 0, 0, 0], 8080)),
+        }
+    }
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        ShutdownConfig {
+            drain_timeout_secs: 25,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: Option<&Path>, overrides: &Overrides) -> Result<Config, ConfigError> {
+        let mut config = match path {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+        config.apply(overrides);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let raw =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        let parse_error =
+            |e: &dyn fmt::Display| ConfigError::Parse(path.to_path_buf(), e.to_string());
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&raw).map_err(|e| parse_error(&e)),
+            // YAML is a superset of JSON, so .json files go through the same parser.
+            _ => serde_yaml::from_str(&raw).map_err(|e| parse_error(&e)),
+        }
+    }
+
+    fn apply(&mut self, overrides: &Overrides) {
+        if let Some(level) = &overrides.log_level {
+            self.log_level = level.clone();
+        }
+        if let Some(namespaces) = &overrides.watch_namespaces {
+            self.watch_namespaces = namespaces.clone();
+        }
+        if let Some(addr) = overrides.webhook_addr {
+            self.webhook.bind_address = addr;
+        }
+        if let Some(cert) = &overrides.tls_cert {
+            self.webhook.tls.cert_path = Some(cert.clone());
+        }
+        if let Some(key) = &overrides.tls_key {
+            self.webhook.tls.key_path = Some(key.clone());
+        }
+        if let Some(addr) = overrides.metrics_addr {
+            self.metrics.bind_address = addr;
+        }
+        if let Some(identity) = &overrides.leader_identity {
+            self.leader_election.identity = Some(identity.clone());
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if let Err(e) = EnvFilter::try_new(&self.log_level) {
+            problems.push(format!(
+                "logLevel {:?} is not a valid filter: {e}",
+                self.log_level
+            ));
+        }
+        for ns in &self.watch_namespaces {
+            if !is_dns_label(ns) {
+                problems.push(format!(
+                    "watchNamespaces: {ns:?} is not a valid namespace name"
+                ));
+            }
+        }
+        if self.webhook.tls.cert_path.is_some() != self.webhook.tls.key_path.is_some() {
+            problems.push("webhook.tls: certPath and keyPath must be set together".to_string());
+        }
+        if self.webhook.tls.reload_interval_secs == 0 {
+            problems.push("webhook.tls.reloadIntervalSecs must be greater than zero".to_string());
+        }
+        let bootstrap = &self.webhook.bootstrap;
+        if bootstrap.enabled {
+            if self.webhook.tls.cert_path.is_none() {
+                problems.push(
+                    "webhook.bootstrap requires webhook.tls.certPath and keyPath to write the certificate to"
+                        .to_string(),
+                );
+            }
+            if bootstrap.rotate_before_days >= bootstrap.validity_days {
+                problems.push(
+                    "webhook.bootstrap.rotateBeforeDays must be less than validityDays".to_string(),
+                );
+            }
+            if bootstrap.check_interval_secs == 0 {
+                problems.push(
+                    "webhook.bootstrap.checkIntervalSecs must be greater than zero".to_string(),
+                );
+            }
+        }
+        if self.controller.requeue_interval_secs == 0 {
+            problems.push("controller.requeueIntervalSecs must be greater than zero".to_string());
+        }
+        if self.controller.error_requeue_interval_secs == 0 {
+            problems
+                .push("controller.errorRequeueIntervalSecs must be greater than zero".to_string());
+        }
+        if self.controller.audit_interval_secs == 0 {
+            problems.push("controller.auditIntervalSecs must be greater than zero".to_string());
+        }
+        if self.metrics.enabled && self.metrics.bind_address == self.webhook.bind_address {
+            problems.push("metrics.bindAddress must differ from webhook.bindAddress".to_string());
+        }
+        let election = &self.leader_election;
+        if election.enabled {
+            if !is_dns_label(&election.namespace) {
+                problems.push(format!(
+                    "leaderElection.namespace: {:?} is not a valid namespace name",
+                    election.namespace
+                ));
+            }
+            if election.lease_name.is_empty() {
+                problems.push("leaderElection.leaseName must not be empty".to_string());
+            }
+            if election.identity.as_deref() == Some("") {
+                problems.push("leaderElection.identity must not be empty".to_string());
+            }
+            if election.retry_period_secs == 0 {
+                problems
+                    .push("leaderElection.retryPeriodSecs must be greater than zero".to_string());
+            }
+            if election.renew_deadline_secs <= election.retry_period_secs {
+                problems.push(
+                    "leaderElection.renewDeadlineSecs must be greater than retryPeriodSecs"
+                        .to_string(),
+                );
+            }
+            if election.lease_duration_secs <= election.renew_deadline_secs {
+                problems.push(
+                    "leaderElection.leaseDurationSecs must be greater than renewDeadlineSecs"
+                        .to_string(),
+                );
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+impl Config {
+    /// Whether objects in `namespace` are governed. Cluster-scoped objects
+    /// always are, and every namespace is when none are listed.
+    pub fn governs(&self, namespace: Option<&str>) -> bool {
+        match namespace {
+            Some(ns) => {
+                self.watch_namespaces.is_empty()
+                    || self.watch_namespaces.iter().any(|watched| watched == ns)
+            }
+            None => true,
+        }
+    }
+}
+
+impl ControllerConfig {
+    pub fn requeue_interval(&self) -> Duration {
+        Duration::from_secs(self.requeue_interval_secs)
+    }
+
+    pub fn error_requeue_interval(&self) -> Duration {
+        Duration::from_secs(self.error_requeue_interval_secs)
+    }
+
+    pub fn audit_interval(&self) -> Duration {
+        Duration::from_secs(self.audit_interval_secs)
+    }
+}
+
+impl TlsConfig {
+    pub fn reload_interval(&self) -> Duration {
+        Duration::from_secs(self.reload_interval_secs)
+    }
+}
+
+impl BootstrapConfig {
+    pub fn check_interval(&self) -> Duration {
+        Duration::from_secs(self.check_interval_secs)
+    }
+}
+
+impl LeaderElectionConfig {
+    /// The configured identity, else the pod or host name. Two replicas
+    /// with the same identity would both consider themselves leader, so the
+    /// last resort is unique to this process.
+    pub fn identity(&self) -> String {
+        self.identity
+            .clone()
+            .or_else(|| std::env::var("POD_NAME").ok())
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .filter(|identity| !identity.is_empty())
+            .unwrap_or_else(|| format!("kube-guardian-{:08x}", rand::random::<u32>()))
+    }
+
+    pub fn lease_duration(&self) -> Duration {
+        Duration::from_secs(self.lease_duration_secs)
+    }
+
+    pub fn renew_deadline(&self) -> Duration {
+        Duration::from_secs(self.renew_deadline_secs)
+    }
+
+    pub fn retry_period(&self) -> Duration {
+        Duration::from_secs(self.retry_period_secs)
+    }
+}
+
+impl ShutdownConfig {
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.drain_timeout_secs)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "cannot read {}: {e}", path.display()),
+            ConfigError::Parse(path, e) => write!(f, "cannot parse {}: {e}", path.display()),
+            ConfigError::Invalid(problems) => {
+                write!(f, "invalid configuration: {}", problems.join("; "))
+            }
+        }
+    }
+}
+
+// `main` reports errors through `Debug`, so keep it as readable as `Display`.
+impl fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn is_dns_label(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 63
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::Parser;
+
+    /// Writes `contents` to a file unique to this test run.
+    fn file(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("kube-guardian-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn load(path: &Path, args: &[&str]) -> Result<Config, ConfigError> {
+        let cli =
+            Cli::try_parse_from(["kube-guardian"].iter().chain(args).chain(&["run"])).unwrap();
+        Config::load(Some(path), &cli.overrides)
+    }
+
+    fn problems(config: &Config) -> Vec<String> {
+        match config.validate() {
+            Ok(()) => Vec::new(),
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
+
+    #[test]
+    fn yaml_and_toml_files_parse_alike() {
+        let yaml = file(
+            "config.yaml",
+            "logLevel: debug\n\
+             watchNamespaces: [team-a]\n\
+             webhook:\n  bindAddress: 0.0.0.0:9443\n\
+             controller:\n  auditIntervalSecs: 120\n",
+        );
+        let toml = file(
+            "config.toml",
+            "logLevel = \"debug\"\n\
+             watchNamespaces = [\"team-a\"]\n\
+             [webhook]\nbindAddress = \"0.0.0.0:9443\"\n\
+             [controller]\nauditIntervalSecs = 120\n",
+        );
+        for path in [yaml, toml] {
+            let config = load(&path, &[]).unwrap();
+            assert_eq!(config.log_level, "debug");
+            assert_eq!(config.watch_namespaces, ["team-a"]);
+            assert_eq!(config.webhook.bind_address.port(), 9443);
+            assert_eq!(config.controller.audit_interval_secs, 120);
+            // Unset values keep their defaults.
+            assert_eq!(config.controller.requeue_interval_secs, 300);
+        }
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        for (name, contents) in [
+            ("typo.yaml", "logLevle: debug\n"),
+            (
+                "nested-typo.yaml",
+                "webhook:\n  tls:\n    certFile: /tls/tls.crt\n",
+            ),
+            ("typo.toml", "[controller]\nauditInterval = 60\n"),
+        ] {
+            let error = load(&file(name, contents), &[]).unwrap_err();
+            assert!(matches!(error, ConfigError::Parse(..)), "{name}: {error}");
+            assert!(
+                error.to_string().contains("unknown field"),
+                "{name}: {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn missing_files_cannot_be_read() {
+        let error = load(Path::new("/nonexistent/kube-guardian.yaml"), &[]).unwrap_err();
+        assert!(matches!(error, ConfigError::Read(..)), "{error}");
+    }
+
+    #[test]
+    fn flags_override_the_file() {
+        let path = file(
+            "overridden.yaml",
+            "logLevel: debug\nwatchNamespaces: [team-a]\nwebhook:\n  bindAddress: 0.0.0.0:9443\n",
+        );
+        let config = load(
+            &path,
+            &[
+                "--log-level",
+                "warn",
+                "--namespace",
+                "team-b,team-c",
+                "--webhook-addr",
+                "127.0.0.1:8443",
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.log_level, "warn");
+        assert_eq!(config.watch_namespaces, ["team-b", "team-c"]);
+        assert_eq!(config.webhook.bind_address.to_string(), "127.0.0.1:8443");
+    }
+
+    #[test]
+    fn environment_variables_override_the_file() {
+        let path = file("identity.yaml", "leaderElection:\n  identity: from-file\n");
+        assert_eq!(
+            load(&path, &[])
+                .unwrap()
+                .leader_election
+                .identity
+                .as_deref(),
+            Some("from-file")
+        );
+        // SAFETY: no other test reads or writes this variable.
+        unsafe { std::env::set_var("KUBE_GUARDIAN_LEADER_IDENTITY", "from-env") };
+        let from_env = load(&path, &[]);
+        let from_flag = load(&path, &["--leader-identity", "from-flag"]);
+        unsafe { std::env::remove_var("KUBE_GUARDIAN_LEADER_IDENTITY") };
+        assert_eq!(
+            from_env.unwrap().leader_election.identity.as_deref(),
+            Some("from-env")
+        );
+        assert_eq!(
+            from_flag.unwrap().leader_election.identity.as_deref(),
+            Some("from-flag")
+        );
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(problems(&Config::default()).is_empty());
+    }
+
+    #[test]
+    fn validation_reports_every_problem() {
+        type Edit = fn(&mut Config);
+        let cases: &[(Edit, &str)] = &[
+            (
+                |c| c.log_level = "info,kube=loud".to_string(),
+                "logLevel \"info,kube=loud\" is not a valid filter",
+            ),
+            (
+                |c| c.watch_namespaces = vec!["Team_A".to_string()],
+                "watchNamespaces: \"Team_A\" is not a valid namespace name",
+            ),
+            (
+                |c| c.webhook.tls.cert_path = Some("/tls/tls.crt".into()),
+                "webhook.tls: certPath and keyPath must be set together",
+            ),
+            (
+                |c| c.webhook.tls.reload_interval_secs = 0,
+                "webhook.tls.reloadIntervalSecs must be greater than zero",
+            ),
+            (
+                |c| c.webhook.bootstrap.enabled = true,
+                "webhook.bootstrap requires webhook.tls.certPath and keyPath to write the certificate to",
+            ),
+            (
+                |c| {
+                    c.webhook.bootstrap.enabled = true;
+                    c.webhook.bootstrap.rotate_before_days = 90;
+                },
+                "webhook.bootstrap.rotateBeforeDays must be less than validityDays",
+            ),
+            (
+                |c| {
+                    c.webhook.bootstrap.enabled = true;
+                    c.webhook.bootstrap.check_interval_secs = 0;
+                },
+                "webhook.bootstrap.checkIntervalSecs must be greater than zero",
+            ),
+            (
+                |c| c.controller.requeue_interval_secs = 0,
+                "controller.requeueIntervalSecs must be greater than zero",
+            ),
+            (
+                |c| c.controller.error_requeue_interval_secs = 0,
+                "controller.errorRequeueIntervalSecs must be greater than zero",
+            ),
+            (
+                |c| c.controller.audit_interval_secs = 0,
+                "controller.auditIntervalSecs must be greater than zero",
+            ),
+            (
+                |c| c.metrics.bind_address = c.webhook.bind_address,
+                "metrics.bindAddress must differ from webhook.bindAddress",
+            ),
+            (
+                |c| c.leader_election.namespace = "-guardian".to_string(),
+                "leaderElection.namespace: \"-guardian\" is not a valid namespace name",
+            ),
+            (
+                |c| c.leader_election.lease_name = String::new(),
+                "leaderElection.leaseName must not be empty",
+            ),
+            (
+                |c| c.leader_election.identity = Some(String::new()),
+                "leaderElection.identity must not be empty",
+            ),
+            (
+                |c| c.leader_election.retry_period_secs = 0,
+                "leaderElection.retryPeriodSecs must be greater than zero",
+            ),
+            (
+                |c| c.leader_election.renew_deadline_secs = 2,
+                "leaderElection.renewDeadlineSecs must be greater than retryPeriodSecs",
+            ),
+            (
+                |c| c.leader_election.lease_duration_secs = 10,
+                "leaderElection.leaseDurationSecs must be greater than renewDeadlineSecs",
+            ),
+        ];
+        for (edit, expected) in cases {
+            let mut config = Config::default();
+            edit(&mut config);
+            let found = problems(&config);
+            assert!(
+                found.iter().any(|problem| problem.starts_with(expected)),
+                "expected {expected:?} in {found:?}"
+            );
+        }
+
+        // Disabled sections are not checked.
+        let mut config = Config::default();
+        config.webhook.bootstrap.rotate_before_days = 90;
+        config.leader_election.lease_name = String::new();
+        config.metrics.enabled = false;
+        config.metrics.bind_address = config.webhook.bind_address;
+        config.leader_election.enabled = false;
+        assert!(problems(&config).is_empty());
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let mut config = Config::default();
+        config.controller.requeue_interval_secs = 0;
+        config.controller.audit_interval_secs = 0;
+        let error = config.validate().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid configuration: controller.requeueIntervalSecs must be greater than zero; \
+             controller.auditIntervalSecs must be greater than zero"
+        );
+    }
+
+    #[test]
+    fn governs_listed_namespaces_and_cluster_scoped_objects() {
+        let mut config = Config::default();
+        assert!(config.governs(Some("team-b")));
+        assert!(config.governs(None));
+
+        config.watch_namespaces = vec!["team-a".to_string()];
+        assert!(config.governs(Some("team-a")));
+        assert!(!config.governs(Some("team-b")));
+        assert!(config.governs(None));
+    }
+
+    #[test]
+    fn bootstrap_failure_policy_is_fail_or_ignore() {
+        let parse = |policy: &str| {
+            let yaml = format!("webhook:\n  bootstrap:\n    failurePolicy: {policy}\n");
+            serde_yaml::from_str::<Config>(&yaml).map(|c| c.webhook.bootstrap.failure_policy)
+        };
+        assert_eq!(
+            Config::default().webhook.bootstrap.failure_policy,
+            FailurePolicy::Ignore
+        );
+        assert_eq!(parse("Fail").unwrap(), FailurePolicy::Fail);
+        assert!(parse("Deny").is_err());
+    }
+}
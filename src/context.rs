@@ -0,0 +1,33 @@
+use kube::Client;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::error::Attempts;
+use crate::events::EventRecorder;
+use crate::governance::cache::PolicyCache;
+use crate::metrics::Metrics;
+
+/// State shared by the reconciler, the background scanner and the webhook,
+/// so all of them work from the same compiled view of the active policies.
+pub struct Context {
+    pub client: Client,
+    pub config: Config,
+    pub policies: PolicyCache,
+    pub metrics: Metrics,
+    pub recorder: EventRecorder,
+    /// Consecutive reconcile failures per policy, driving the retry backoff.
+    pub attempts: Attempts,
+}
+
+impl Context {
+    pub fn new(client: Client, config: Config) -> Arc<Context> {
+        Arc::new(Context {
+            recorder: EventRecorder::new(client.clone()),
+            client,
+            config,
+            policies: PolicyCache::default(),
+            metrics: Metrics::default(),
+            attempts: Attempts::default(),
+        })
+    }
+}
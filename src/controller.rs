@@ -1,24 +1,480 @@
 use futures::StreamExt;
+use k8s_openapi::api::core::v1::{Namespace, ObjectReference};
+use kube::api::{ApiResource, DynamicObject, Patch, PatchParams, TypeMeta};
+use kube::discovery::{Discovery, verbs};
 use kube::runtime::controller::Controller;
-use kube::runtime::watcher::Config;
-use kube::{Api, Client};
+use kube::runtime::reflector::store::Writer;
+use kube::runtime::reflector::{self, ObjectRef, Store};
+use kube::runtime::utils::Predicate;
+use kube::runtime::{WatchStreamExt, predicates, watcher};
+use kube::{Api, Resource, ResourceExt};
+use serde_json::{Value, json};
+use std::collections::{BTreeSet, HashMap};
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
 
-use crate::crd::GuardianPolicy;
+use crate::context::Context;
+use crate::crd::{AUDIT_FIELD_MANAGER, EnforcementAction, GuardianPolicy};
+use crate::error::{Error, Result};
+use crate::events::POLICY_VIOLATION;
+use crate::governance::cel::CompiledPolicy;
+use crate::governance::policies::Target;
+use crate::governance::traits::{EvaluationContext, Outcome, PolicyEvaluator};
+use crate::metrics::ViolationLabels;
 use crate::reconcile::{error_policy, reconcile};
+use crate::reports::{self, Desired, ReportKey, ReportResult, Reports, ResourceRef};
+use crate::shutdown::TaskResult;
+use crate::webhook;
 
-pub async fn run_controller(client: Client) -> Result<(), Box<dyn std::error::Error>> {
-    let api: Api<GuardianPolicy> = Api::all(client);
+/// How long a newly watched kind may take to list before a scan goes ahead
+/// without it.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(30);
 
-    Controller::new(api, Config::default())
-        .run(reconcile, error_policy, Arc::new(()))
+/// Runs the GuardianPolicy controller and the background audit scanner until
+/// `shutdown` is cancelled, letting in-flight reconciles finish first.
+pub async fn run_controller(ctx: Arc<Context>, shutdown: CancellationToken) -> TaskResult {
+    let client = ctx.client.clone();
+    let api: Api<GuardianPolicy> = Api::all(client.clone());
+
+    // Only spec, finalizer and deletion changes trigger a reconcile; otherwise
+    // the reconciler's own status patches would wake it up again immediately.
+    let (reader, writer) = reflector::store();
+    let changed = Arc::new(Notify::new());
+    let notify = changed.clone();
+    let (cache, store) = (ctx.policies.clone(), reader.clone());
+    let policies = watcher(api, watcher::Config::default())
+        .default_backoff()
+        .reflect(writer)
+        .inspect(move |event| {
+            if let Ok(event) = event {
+                cache.prune(event, &store);
+            }
+            notify.notify_one();
+        })
+        .applied_objects()
+        .predicate_filter(
+            predicates::generation
+                .combine(predicates::finalizers)
+                .combine(deleting),
+        );
+
+    let scanner = Scanner {
+        ctx: ctx.clone(),
+        policies: reader.clone(),
+        namespaces: webhook::mirror(Api::<Namespace>::all(client.clone()), &shutdown),
+        watched: HashMap::new(),
+        counts: HashMap::new(),
+        reports: Reports::new(client),
+        reports_loaded: false,
+    };
+    let audit = tokio::spawn(scanner.run(changed, shutdown.clone()));
+
+    Controller::for_stream(policies, reader)
+        .graceful_shutdown_on(shutdown.cancelled_owned())
+        .run(reconcile, error_policy, ctx)
         .for_each(|res| async move {
             match res {
-                Ok(obj) => println!("Reconciled: {:?}", obj),
-                Err(e) => eprintln!("Reconcile error: {:?}", e),
+                Ok(obj) => info!("Reconciled: {:?}", obj),
+                Err(e) => warn!("Reconcile error: {:?}", e),
             }
         })
         .await;
 
+    audit.await?;
+    info!("Controller stopped");
     Ok(())
 }
+
+fn deleting(policy: &GuardianPolicy) -> Option<u64> {
+    policy.metadata.deletion_timestamp.as_ref().map(|_| 1)
+}
+
+/// Evaluates existing resources of every kind the active policies name
+/// explicitly in `match.kinds`, and records the totals in policy status.
+struct Scanner {
+    ctx: Arc<Context>,
+    policies: Store<GuardianPolicy>,
+    namespaces: Store<Namespace>,
+    /// A reflector per scanned kind, keyed by API group and kind.
+    watched: HashMap<(String, String), Watched>,
+    /// The counts last written to each policy's status.
+    counts: HashMap<String, Counts>,
+    reports: Reports,
+    /// Whether reports left by a previous run have been listed.
+    reports_loaded: bool,
+}
+
+struct Watched {
+    resource: ApiResource,
+    store: Store<DynamicObject>,
+    stop: CancellationToken,
+}
+
+/// What one scan found, gathered before anything is written.
+#[derive(Default)]
+struct Findings {
+    counts: HashMap<String, Counts>,
+    reports: Desired,
+    /// Violating objects, each with an event note per failed rule.
+    violations: Vec<(ObjectReference, String)>,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Counts {
+    compliant: u32,
+    violating: u32,
+}
+
+/// What a policy change must alter for the scanner to run again: reconcile
+/// status writes do not count.
+type Fingerprint = BTreeSet<(String, Option<String>, Option<i64>, bool)>;
+
+impl Scanner {
+    async fn run(mut self, changed: Arc<Notify>, shutdown: CancellationToken) {
+        tokio::select! {
+            res = async {
+                self.policies.wait_until_ready().await?;
+                self.namespaces.wait_until_ready().await
+            } => if res.is_err() {
+                return;
+            },
+            _ = shutdown.cancelled() => return,
+        }
+
+        let mut ticker = tokio::time::interval(self.ctx.config.controller.audit_interval());
+        let mut scanned = Fingerprint::new();
+        loop {
+            let scheduled = tokio::select! {
+                _ = ticker.tick() => true,
+                _ = changed.notified() => false,
+                _ = shutdown.cancelled() => break,
+            };
+            let fingerprint = self.fingerprint();
+            if !scheduled && fingerprint == scanned {
+                continue;
+            }
+            scanned = fingerprint;
+            if let Err(e) = self.scan().await {
+                warn!("Background scan failed: {e}");
+            }
+        }
+
+        for watched in self.watched.values() {
+            watched.stop.cancel();
+        }
+        // Another replica's scanner reports from now on.
+        self.ctx.metrics.violations.clear();
+        info!("Background scanner stopped");
+    }
+
+    fn fingerprint(&self) -> Fingerprint {
+        self.policies
+            .state()
+            .iter()
+            .map(|policy| {
+                (
+                    policy.name_any(),
+                    policy.uid(),
+                    policy.metadata.generation,
+                    policy.metadata.deletion_timestamp.is_some(),
+                )
+            })
+            .collect()
+    }
+
+    async fn scan(&mut self) -> Result<()> {
+        let mut policies = self.ctx.policies.compile_all(&self.policies.state());
+        // Invalid policies are reported through their status, not enforced.
+        policies.retain(|compiled| compiled.policy.enabled && compiled.is_valid());
+        let discovery = Discovery::new(self.ctx.client.clone()).run().await?;
+        self.watch_kinds(&policies, &discovery).await;
+
+        let reporting = discovery.has_group(reports::GROUP);
+        if reporting && !self.reports_loaded {
+            match self.reports.load().await {
+                Ok(()) => self.reports_loaded = true,
+                Err(e) => warn!("Cannot list existing policy reports: {e}"),
+            }
+        }
+
+        let mut findings = Findings {
+            counts: policies
+                .iter()
+                .map(|compiled| (compiled.policy.name.clone(), Counts::default()))
+                .collect(),
+            ..Findings::default()
+        };
+        let mut scanned = 0;
+        for watched in self.watched.values() {
+            for object in watched.store.state() {
+                if !self.ctx.config.governs(object.namespace().as_deref()) {
+                    continue;
+                }
+                scanned += 1;
+                if let Err(e) = self.evaluate(&watched.resource, &object, &policies, &mut findings)
+                {
+                    warn!(
+                        "Skipping {} {}: {e}",
+                        watched.resource.kind,
+                        object.name_any()
+                    );
+                }
+            }
+        }
+        info!(
+            "Scanned {scanned} resource(s) of {} kind(s) against {} policy(ies)",
+            self.watched.len(),
+            policies.len()
+        );
+
+        for (regarding, note) in &findings.violations {
+            self.ctx
+                .recorder
+                .warning(regarding, POLICY_VIOLATION, "Audit", note)
+                .await;
+        }
+
+        let counts = findings.counts;
+        self.ctx.metrics.violations.clear();
+        for compiled in &policies {
+            let policy = &compiled.policy;
+            if policy.enforcement_action != EnforcementAction::DryRun {
+                let labels = ViolationLabels {
+                    policy: policy.name.clone(),
+                    severity: reports::severity(policy.severity),
+                };
+                let violating = counts[&policy.name].violating;
+                self.ctx
+                    .metrics
+                    .violations
+                    .get_or_create(&labels)
+                    .set(violating.into());
+            }
+        }
+        self.counts.retain(|name, _| counts.contains_key(name));
+        for compiled in &policies {
+            let policy = &compiled.policy;
+            // Dry-run policies only log what they find.
+            if policy.enforcement_action == EnforcementAction::DryRun {
+                continue;
+            }
+            let current = counts[&policy.name];
+            if self.counts.get(&policy.name) == Some(&current) {
+                continue;
+            }
+            match self.record(&policy.name, current).await {
+                Ok(()) => {
+                    self.counts.insert(policy.name.clone(), current);
+                }
+                Err(e) => warn!("Cannot record scan results of {}: {e}", policy.name),
+            }
+        }
+
+        if reporting {
+            let owners: HashMap<String, String> = self
+                .policies
+                .state()
+                .iter()
+                .filter_map(|policy| Some((policy.name_any(), policy.uid()?)))
+                .collect();
+            self.reports.sync(findings.reports, &owners).await;
+        } else {
+            debug!(
+                "The {} CRDs are not installed; skipping policy reports",
+                reports::GROUP
+            );
+        }
+        Ok(())
+    }
+
+    /// Starts reflectors for newly referenced kinds and stops those no
+    /// policy names any more.
+    async fn watch_kinds(&mut self, policies: &[Arc<CompiledPolicy>], discovery: &Discovery) {
+        let mut wanted: Vec<(Vec<String>, String)> = Vec::new();
+        for compiled in policies {
+            for rule in &compiled.policy.rules {
+                let selector = &rule.match_resources;
+                if selector.kinds.is_empty() {
+                    debug!(
+                        "Rule {}/{} names no kinds and is only checked at admission",
+                        compiled.policy.name, rule.name
+                    );
+                }
+                for kind in selector.kinds.iter().filter(|kind| *kind != "*") {
+                    wanted.push((selector.api_groups.clone(), kind.clone()));
+                }
+            }
+        }
+
+        let mut resources = HashMap::new();
+        for group in discovery.groups() {
+            for (groups, kind) in &wanted {
+                let selected =
+                    groups.is_empty() || groups.iter().any(|g| g == "*" || g == group.name());
+                if !selected {
+                    continue;
+                }
+                if let Some((resource, caps)) = group.recommended_kind(kind)
+                    && caps.supports_operation(verbs::LIST)
+                    && caps.supports_operation(verbs::WATCH)
+                {
+                    resources.insert((resource.group.clone(), resource.kind.clone()), resource);
+                }
+            }
+        }
+
+        self.watched.retain(|key, watched| {
+            let keep = resources.contains_key(key);
+            if !keep {
+                info!("No longer scanning {}", watched.resource.kind);
+                watched.stop.cancel();
+            }
+            keep
+        });
+        for (key, resource) in resources {
+            if self.watched.contains_key(&key) {
+                continue;
+            }
+            info!("Scanning {} ({})", resource.kind, resource.api_version);
+            let watched = self.mirror(resource);
+            if tokio::time::timeout(SYNC_TIMEOUT, watched.store.wait_until_ready())
+                .await
+                .is_err()
+            {
+                warn!(
+                    "{} has not listed after {}s; scanning what has arrived",
+                    watched.resource.kind,
+                    SYNC_TIMEOUT.as_secs()
+                );
+            }
+            self.watched.insert(key, watched);
+        }
+    }
+
+    fn mirror(&self, resource: ApiResource) -> Watched {
+        let api = Api::<DynamicObject>::all_with(self.ctx.client.clone(), &resource);
+        let writer = Writer::new(resource.clone());
+        let store = writer.as_reader();
+        let watch = watcher(api, watcher::Config::default())
+            .default_backoff()
+            .reflect(writer)
+            .for_each(|_| futures::future::ready(()));
+        let stop = CancellationToken::new();
+        let token = stop.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = watch => {}
+                _ = token.cancelled() => {}
+            }
+        });
+        Watched {
+            resource,
+            store,
+            stop,
+        }
+    }
+
+    fn evaluate(
+        &self,
+        resource: &ApiResource,
+        object: &DynamicObject,
+        policies: &[Arc<CompiledPolicy>],
+        findings: &mut Findings,
+    ) -> Result<()> {
+        // List responses may leave out the type of each item.
+        let mut object = object.clone();
+        object.types = Some(TypeMeta {
+            api_version: resource.api_version.clone(),
+            kind: resource.kind.clone(),
+        });
+        let value = serde_json::to_value(&object).map_err(|e| Error::Evaluation(e.to_string()))?;
+        let target = Target::from_object(&value);
+        let namespace_object: Option<Value> = target.namespace.as_ref().and_then(|ns| {
+            let namespace = self.namespaces.get(&ObjectRef::new(ns))?;
+            serde_json::to_value(namespace.as_ref()).ok()
+        });
+        let context = EvaluationContext {
+            namespace_object: namespace_object.as_ref(),
+            ..EvaluationContext::new(&target)
+        };
+
+        let reference = ResourceRef {
+            api_version: resource.api_version.clone(),
+            kind: resource.kind.clone(),
+            name: object.name_any(),
+            namespace: object.namespace(),
+            uid: object.uid().unwrap_or_default(),
+        };
+        for compiled in policies {
+            let result = compiled.evaluate(&value, &context);
+            if result.enforcement_action != EnforcementAction::DryRun {
+                let key = ReportKey {
+                    namespace: reference.namespace.clone(),
+                    policy: result.policy.clone(),
+                };
+                for rule in &result.rules {
+                    let Some(entry) =
+                        ReportResult::new(rule, result.enforcement_action, reference.clone())
+                    else {
+                        continue;
+                    };
+                    findings
+                        .reports
+                        .entry(key.clone())
+                        .or_default()
+                        .insert((rule.rule.clone(), reference.uid.clone()), entry);
+                }
+            }
+            let failures: Vec<String> = result.failures().map(ToString::to_string).collect();
+            let selected = result
+                .rules
+                .iter()
+                .any(|rule| matches!(rule.outcome, Outcome::Pass | Outcome::Fail));
+            if !selected {
+                continue;
+            }
+            let subject = match object.namespace() {
+                Some(ns) => format!("{} {ns}/{}", resource.kind, object.name_any()),
+                None => format!("{} {}", resource.kind, object.name_any()),
+            };
+            let count = findings.counts.entry(result.policy.clone()).or_default();
+            if failures.is_empty() {
+                count.compliant += 1;
+            } else if result.enforcement_action == EnforcementAction::DryRun {
+                info!("Dry run: {subject} violates {}", failures.join("; "));
+            } else {
+                count.violating += 1;
+                debug!("{subject} violates {}", failures.join("; "));
+                let regarding = object.object_ref(resource);
+                findings.violations.extend(
+                    result
+                        .failures()
+                        .map(|failure| (regarding.clone(), failure.to_string())),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn record(&self, policy: &str, counts: Counts) -> Result<(), kube::Error> {
+        let api: Api<GuardianPolicy> = Api::all(self.ctx.client.clone());
+        let patch = json!({
+            "apiVersion": "guardian.io/v1",
+            "kind": "GuardianPolicy",
+            "status": {
+                "compliantResources": counts.compliant,
+                "violatingResources": counts.violating,
+            },
+        });
+        api.patch_status(
+            policy,
+            &PatchParams::apply(AUDIT_FIELD_MANAGER).force(),
+            &Patch::Apply(&patch),
+        )
+        .await?;
+        Ok(())
+    }
+}
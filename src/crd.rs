@@ -1,9 +1,384 @@
-use kube::CustomResource;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, LabelSelector, Time};
+use kube::api::{Patch, PatchParams};
+use kube::runtime::wait::{await_condition, conditions};
+use kube::{Api, Client, CustomResource, CustomResourceExt, ResourceExt};
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::cli::OutputFormat;
+
+pub const FIELD_MANAGER: &str = "kube-guardian";
+/// Owns the resource counts in GuardianPolicy status, so reconciles and scans
+/// never overwrite each other's fields.
+pub const AUDIT_FIELD_MANAGER: &str = "kube-guardian-audit";
 
 #[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
 #[kube(group = "guardian.io", version = "v1", kind = "GuardianPolicy")]
+#[kube(status = "GuardianPolicyStatus")]
+#[kube(shortname = "gp", category = "guardian")]
+#[kube(printcolumn = r#"{"name":"Severity","type":"string","jsonPath":".spec.severity"}"#)]
+#[kube(
+    printcolumn = r#"{"name":"Enforcement","type":"string","jsonPath":".spec.enforcementAction"}"#
+)]
+#[kube(
+    printcolumn = r#"{"name":"Ready","type":"string","jsonPath":".status.conditions[?(@.type==\"Ready\")].status"}"#
+)]
+#[kube(
+    printcolumn = r#"{"name":"Violations","type":"integer","jsonPath":".status.violatingResources"}"#
+)]
+#[kube(printcolumn = r#"{"name":"Age","type":"date","jsonPath":".metadata.creationTimestamp"}"#)]
+#[serde(rename_all = "camelCase")]
 pub struct GuardianPolicySpec {
-    pub severity: String,
+    pub severity: Severity,
+    /// What happens to resources that violate the policy. Defaults to `deny`.
+    #[serde(default)]
+    pub enforcement_action: EnforcementAction,
+    /// What a `deny` policy does with a request when one of its rules cannot
+    /// be evaluated. Defaults to `Fail`.
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+}
+
+/// Observed state of a GuardianPolicy, written by the reconciler.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GuardianPolicyStatus {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<Condition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observed_generation: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_evaluation_time: Option<Time>,
+    /// Resources the last background scan found compliant. Written by the
+    /// scanner under its own field manager.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compliant_resources: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub violating_resources: Option<u32>,
+}
+
+/// A single check within a GuardianPolicy.
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyRule {
+    pub name: String,
+    #[serde(rename = "match", default)]
+    pub match_resources: MatchResources,
+    /// Expression a selected resource must satisfy. Optional for rules that only set defaults.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+    /// Field checks a selected resource must pass, for rules that need no expression.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<FieldPattern>,
+    /// A check from the built-in library, referenced by name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builtin: Option<BuiltinRule>,
+    /// Checks the Pod Security Standards profile of this level.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pod_security: Option<PodSecurityRule>,
+    /// Reported when the condition, a pattern or a built-in check is not met.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub message: String,
+    /// Values the mutating webhook injects into selected resources when they are absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<ResourceDefaults>,
+}
+
+/// A check on every field a JSONPath selects. Exactly one of `exists`,
+/// `absent`, `equals`, `matches` and `oneOf` is set.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldPattern {
+    /// JSONPath to the checked fields, e.g. `spec.containers[*].image`.
+    pub path: String,
+    /// The field must be present.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exists: bool,
+    /// The field must not be present.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub absent: bool,
+    /// The field must equal this value, compared as text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equals: Option<String>,
+    /// The field must match this regular expression.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matches: Option<String>,
+    /// The field must equal one of these values, compared as text.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub one_of: Vec<String>,
+}
+
+/// Enables a built-in check such as `disallow-privileged` or
+/// `restrict-image-registries`.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BuiltinRule {
+    pub name: String,
+    /// Check-specific parameters, e.g. `registries: ["ghcr.io/acme/"]`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, Vec<String>>,
+}
+
+/// Enforces a Kubernetes Pod Security Standards profile.
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PodSecurityRule {
+    pub level: PodSecurityLevel,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PodSecurityLevel {
+    Baseline,
+    Restricted,
+}
+
+/// Defaults applied by the mutating webhook. Existing values are never overwritten.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceDefaults {
+    /// Labels added to the resource's metadata.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Resource requests (e.g. `cpu: 100m`) added to every container that
+    /// sets neither a request nor a limit for the resource.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub resource_requests: BTreeMap<String, String>,
+    /// Pod-level `securityContext.runAsNonRoot`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_as_non_root: Option<bool>,
+    /// `imagePullPolicy` for every container.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_pull_policy: Option<ImagePullPolicy>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum ImagePullPolicy {
+    Always,
+    IfNotPresent,
+    Never,
+}
+
+/// Selects the resources a rule applies to. Empty lists match everything.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchResources {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kinds: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_groups: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub namespaces: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label_selector: Option<LabelSelector>,
+}
+
+/// How serious a violation is, from Info (least) to Critical (most).
+// Variants are declared in ascending order so the derived `Ord` can be used for
+// thresholds such as "block at High and above".
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, JsonSchema)]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 5] = [
+        Severity::Info,
+        Severity::Low,
+        Severity::Medium,
+        Severity::High,
+        Severity::Critical,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "Info",
+            Severity::Low => "Low",
+            Severity::Medium => "Medium",
+            Severity::High => "High",
+            Severity::Critical => "Critical",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Severity::ALL
+            .into_iter()
+            .find(|severity| severity.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                format!("unknown severity {s:?}, expected one of Info, Low, Medium, High, Critical")
+            })
+    }
+}
+
+// The CRD schema only admits the canonical names, so stored policies and the
+// Severity column always read `High`; files checked offline with `evaluate`
+// may use any case.
+impl<'de> Deserialize<'de> for Severity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// How a policy treats the resources that violate it, at admission and in
+/// background scans.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EnforcementAction {
+    /// Reject violating admission requests.
+    #[default]
+    Deny,
+    /// Admit violating requests and return the violations as warnings.
+    Warn,
+    /// Admit violating requests silently and apply no defaults; violations
+    /// only appear in reports.
+    Audit,
+    /// Evaluate and log what would happen, without any other effect.
+    DryRun,
+}
+
+impl EnforcementAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EnforcementAction::Deny => "deny",
+            EnforcementAction::Warn => "warn",
+            EnforcementAction::Audit => "audit",
+            EnforcementAction::DryRun => "dryrun",
+        }
+    }
+}
+
+impl fmt::Display for EnforcementAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Named after the ValidatingAdmissionPolicy field with the same meaning.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub enum FailurePolicy {
+    /// Deny the request, as if the rule had failed.
+    #[default]
+    Fail,
+    /// Admit the request and return the error as a warning.
+    Ignore,
+}
+
+impl FailurePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailurePolicy::Fail => "Fail",
+            FailurePolicy::Ignore => "Ignore",
+        }
+    }
+}
+
+/// Every CustomResourceDefinition owned by kube-guardian.
+pub fn crds() -> Vec<CustomResourceDefinition> {
+    vec![GuardianPolicy::crd()]
+}
+
+pub fn print(format: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let crds = crds();
+    match format {
+        OutputFormat::Yaml => {
+            for crd in &crds {
+                print!("---\n{}", serde_yaml::to_string(crd)?);
+            }
+        }
+        OutputFormat::Json if crds.len() == 1 => {
+            println!("{}", serde_json::to_string_pretty(&crds[0])?)
+        }
+        OutputFormat::Json => {
+            let list = serde_json::json!({ "apiVersion": "v1", "kind": "List", "items": crds });
+            println!("{}", serde_json::to_string_pretty(&list)?)
+        }
+    }
+    Ok(())
+}
+
+/// Server-side applies every CRD and waits for the API server to mark it Established.
+pub async fn install(client: Client, timeout: Duration) -> Result<(), Box<dyn std::error::Error>> {
+    let api: Api<CustomResourceDefinition> = Api::all(client);
+    let params = PatchParams::apply(FIELD_MANAGER).force();
+
+    for crd in crds() {
+        let name = crd.name_any();
+        api.patch(&name, &params, &Patch::Apply(&crd)).await?;
+        println!("Applied CustomResourceDefinition {name}");
+
+        let established = await_condition(api.clone(), &name, conditions::is_crd_established());
+        tokio::time::timeout(timeout, established)
+            .await
+            .map_err(|_| format!("timed out waiting for {name} to become Established"))??;
+        println!("CustomResourceDefinition {name} is Established");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn severity_parses_in_any_case() {
+        assert_eq!("High".parse(), Ok(Severity::High));
+        assert_eq!("high".parse(), Ok(Severity::High));
+        assert_eq!("CRITICAL".parse(), Ok(Severity::Critical));
+        assert_eq!(" info ".parse(), Ok(Severity::Info));
+        assert_eq!(
+            "severe".parse::<Severity>(),
+            Err(
+                "unknown severity \"severe\", expected one of Info, Low, Medium, High, Critical"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn severity_deserializes_case_insensitively() {
+        let severity: Severity = serde_json::from_str("\"medium\"").unwrap();
+        assert_eq!(severity, Severity::Medium);
+        assert!(serde_json::from_str::<Severity>("\"urgent\"").is_err());
+    }
+
+    #[test]
+    fn severities_are_ordered_from_info_to_critical() {
+        assert!(Severity::ALL.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(Severity::High >= Severity::Medium);
+        assert_eq!(Severity::ALL.iter().max(), Some(&Severity::Critical));
+    }
+
+    #[test]
+    fn severity_schema_is_an_enum_of_canonical_names() {
+        let schema = serde_json::to_value(schemars::schema_for!(Severity)).unwrap();
+        assert_eq!(
+            schema["enum"],
+            serde_json::json!(["Info", "Low", "Medium", "High", "Critical"])
+        );
+        for severity in Severity::ALL {
+            assert_eq!(severity.as_str().parse(), Ok(severity));
+        }
+    }
 }
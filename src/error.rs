@@ -0,0 +1,205 @@
+use kube::runtime::finalizer;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Why reconciling a policy or scanning the cluster failed. Each variant
+/// calls for a different retry strategy, see [`Error::retry_after`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The policy spec cannot be enforced. Retrying will not help; the
+    /// status says why and the next spec change is reconciled again.
+    #[error("policy {policy} is invalid ({reason}): {message}")]
+    InvalidPolicy {
+        policy: String,
+        reason: &'static str,
+        message: String,
+    },
+
+    /// Another writer changed the object since it was read.
+    #[error("conflicting write: {0}")]
+    Conflict(#[source] kube::Error),
+
+    /// The API server could not be reached or failed the request.
+    #[error("Kubernetes API request failed: {0}")]
+    Api(#[source] kube::Error),
+
+    /// An object could not be prepared or evaluated against the policies.
+    #[error("evaluation failed: {0}")]
+    Evaluation(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<kube::Error> for Error {
+    fn from(e: kube::Error) -> Self {
+        match &e {
+            kube::Error::Api(response) if response.code == 409 => Error::Conflict(e),
+            _ => Error::Api(e),
+        }
+    }
+}
+
+impl From<finalizer::Error<Error>> for Error {
+    fn from(e: finalizer::Error<Error>) -> Self {
+        match e {
+            finalizer::Error::ApplyFailed(e) | finalizer::Error::CleanupFailed(e) => e,
+            finalizer::Error::AddFinalizer(e) | finalizer::Error::RemoveFinalizer(e) => e.into(),
+            e @ (finalizer::Error::UnnamedObject | finalizer::Error::InvalidFinalizer) => {
+                Error::Evaluation(e.to_string())
+            }
+        }
+    }
+}
+
+/// Conflicts are retried immediately this many times before they back off
+/// like any other API failure.
+const IMMEDIATE_RETRIES: u32 = 3;
+
+/// The first transient retry waits about this long; each further attempt
+/// doubles it up to the configured error requeue interval.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+impl Error {
+    /// Label for the reconcile error metric.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::InvalidPolicy { .. } => "invalid_policy",
+            Error::Conflict(_) => "conflict",
+            Error::Api(_) => "api",
+            Error::Evaluation(_) => "evaluation",
+        }
+    }
+
+    /// How long to wait before retrying after the `attempt`th consecutive
+    /// failure (starting at 1), or `None` to wait for the object to change.
+    pub fn retry_after(&self, attempt: u32, max: Duration) -> Option<Duration> {
+        match self {
+            Error::InvalidPolicy { .. } => None,
+            Error::Conflict(_) if attempt <= IMMEDIATE_RETRIES => Some(Duration::ZERO),
+            Error::Conflict(_) => Some(backoff(attempt - IMMEDIATE_RETRIES, max)),
+            Error::Api(_) => Some(backoff(attempt, max)),
+            // The inputs are unlikely to change soon, so don't hammer them.
+            Error::Evaluation(_) => Some(max),
+        }
+    }
+}
+
+/// Exponential backoff with jitter: a random delay between half and all of
+/// `BASE_DELAY * 2^(attempt - 1)`, capped at `max`.
+fn backoff(attempt: u32, max: Duration) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16);
+    let delay = BASE_DELAY.saturating_mul(1 << exponent).min(max);
+    delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+/// Consecutive failed attempts per object, reset once it reconciles.
+#[derive(Default)]
+pub struct Attempts(Mutex<HashMap<String, u32>>);
+
+impl Attempts {
+    /// Records another failure for `key` and returns the attempt count.
+    pub fn failed(&self, key: &str) -> u32 {
+        let mut attempts = self.0.lock().unwrap();
+        let count = attempts.entry(key.to_string()).or_default();
+        *count += 1;
+        *count
+    }
+
+    pub fn succeeded(&self, key: &str) {
+        self.0.lock().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kube::core::ErrorResponse;
+
+    fn api_error(code: u16) -> Error {
+        kube::Error::Api(ErrorResponse {
+            status: "Failure".to_string(),
+            message: "test".to_string(),
+            reason: "Test".to_string(),
+            code,
+        })
+        .into()
+    }
+
+    fn invalid() -> Error {
+        Error::InvalidPolicy {
+            policy: "p".to_string(),
+            reason: "InvalidSpec",
+            message: "bad".to_string(),
+        }
+    }
+
+    const MAX: Duration = Duration::from_secs(300);
+
+    #[test]
+    fn conflicts_are_told_apart_from_other_api_errors() {
+        assert_eq!(api_error(409).kind(), "conflict");
+        assert_eq!(api_error(500).kind(), "api");
+    }
+
+    #[test]
+    fn backoff_is_jittered_between_half_and_the_full_delay() {
+        for attempt in 1..=8 {
+            let delay = BASE_DELAY * (1 << (attempt - 1));
+            for _ in 0..100 {
+                let wait = backoff(attempt, MAX);
+                assert!(wait >= delay / 2 && wait <= delay, "{attempt}: {wait:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped_at_max() {
+        for attempt in [10, 16, 17, 100, u32::MAX] {
+            let wait = backoff(attempt, MAX);
+            assert!(wait >= MAX / 2 && wait <= MAX, "{attempt}: {wait:?}");
+        }
+        let short = Duration::from_secs(3);
+        assert!(backoff(5, short) <= short);
+    }
+
+    #[test]
+    fn conflicts_retry_immediately_three_times_then_back_off() {
+        let conflict = api_error(409);
+        for attempt in 1..=IMMEDIATE_RETRIES {
+            assert_eq!(conflict.retry_after(attempt, MAX), Some(Duration::ZERO));
+        }
+        let wait = conflict.retry_after(IMMEDIATE_RETRIES + 1, MAX).unwrap();
+        assert!(wait >= BASE_DELAY / 2 && wait <= BASE_DELAY, "{wait:?}");
+    }
+
+    #[test]
+    fn api_errors_back_off_from_the_first_attempt() {
+        let wait = api_error(503).retry_after(1, MAX).unwrap();
+        assert!(wait >= BASE_DELAY / 2 && wait <= BASE_DELAY, "{wait:?}");
+    }
+
+    #[test]
+    fn invalid_policies_wait_for_a_change() {
+        assert_eq!(invalid().retry_after(1, MAX), None);
+        assert_eq!(invalid().retry_after(10, MAX), None);
+    }
+
+    #[test]
+    fn evaluation_errors_wait_the_full_interval() {
+        let error = Error::Evaluation("bad object".to_string());
+        assert_eq!(error.retry_after(1, MAX), Some(MAX));
+    }
+
+    #[test]
+    fn attempts_count_until_success() {
+        let attempts = Attempts::default();
+        assert_eq!(attempts.failed("a"), 1);
+        assert_eq!(attempts.failed("a"), 2);
+        assert_eq!(attempts.failed("b"), 1);
+        attempts.succeeded("a");
+        assert_eq!(attempts.failed("a"), 1);
+        assert_eq!(attempts.failed("b"), 2);
+    }
+}
@@ -0,0 +1,91 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::crd::{EnforcementAction, GuardianPolicy};
+use crate::governance::cel::CompiledPolicy;
+use crate::governance::policies::{Policy, Target};
+use crate::governance::traits::{EvaluationContext, Outcome, PolicyEvaluator};
+
+/// Offline evaluation of manifests against GuardianPolicy files, for CI
+/// pipelines and for trying out a policy before applying it.
+pub fn run(policy_files: &[PathBuf], resource_files: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    let mut policies = Vec::new();
+    for path in policy_files {
+        for document in read_documents(path)? {
+            let policy: GuardianPolicy = serde_json::from_value(document)
+                .map_err(|e| format!("{}: not a GuardianPolicy: {e}", path.display()))?;
+            let policy = CompiledPolicy::compile(Policy::from(&policy));
+            let name = &policy.policy.name;
+            if let Err((_, e)) = policy.validation() {
+                return Err(format!("{}: policy {name} is invalid: {e}", path.display()).into());
+            }
+            policies.push(policy);
+        }
+    }
+
+    let (mut failures, mut errors) = (0, 0);
+    for path in resource_files {
+        for object in read_documents(path)? {
+            let target = Target::from_object(&object);
+            let name = object["metadata"]["name"].as_str().unwrap_or("<unnamed>");
+            let context = EvaluationContext::new(&target);
+
+            let results: Vec<_> = policies
+                .iter()
+                .map(|policy| policy.evaluate(&object, &context))
+                .flat_map(|result| {
+                    let action = result.enforcement_action;
+                    result.rules.into_iter().map(move |rule| (action, rule))
+                })
+                .filter(|(_, rule)| matches!(rule.outcome, Outcome::Fail | Outcome::Error))
+                .collect();
+
+            if results.is_empty() {
+                println!("PASS {}/{name}", target.kind);
+            }
+            for (action, result) in &results {
+                // Only violations of deny policies would be rejected.
+                let label = match (result.outcome, action) {
+                    (Outcome::Fail, EnforcementAction::Deny) => {
+                        failures += 1;
+                        "FAIL"
+                    }
+                    (Outcome::Fail, EnforcementAction::Warn) => "WARN",
+                    (Outcome::Fail, EnforcementAction::Audit) => "AUDIT",
+                    (Outcome::Fail, EnforcementAction::DryRun) => "DRYRUN",
+                    _ => {
+                        errors += 1;
+                        "ERROR"
+                    }
+                };
+                println!("{label} {}/{name}: {result}", target.kind);
+            }
+        }
+    }
+
+    // A rule that cannot be evaluated proves nothing, so it fails the run too.
+    if failures + errors > 0 {
+        return Err(
+            format!("{failures} violation(s) and {errors} evaluation error(s) found").into(),
+        );
+    }
+    Ok(())
+}
+
+/// Reads every non-empty document from a multi-document YAML (or JSON) file.
+fn read_documents(path: &Path) -> Result<Vec<Value>, Box<dyn Error>> {
+    let raw = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+
+    let mut documents = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(&raw) {
+        let value = Value::deserialize(document)
+            .map_err(|e| format!("cannot parse {}: {e}", path.display()))?;
+        if !value.is_null() {
+            documents.push(value);
+        }
+    }
+    Ok(documents)
+}
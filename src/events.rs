@@ -0,0 +1,208 @@
+use k8s_openapi::api::core::v1::{Event as CoreEvent, ObjectReference};
+use kube::api::{DeleteParams, ListParams};
+use kube::runtime::events::{Event, EventType, Recorder, Reporter};
+use kube::{Api, Client};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How long an identical event for the same object is suppressed. Repeats
+/// after that are folded into the existing Event series by the recorder.
+const DEDUP_WINDOW: Duration = Duration::from_secs(3600);
+
+/// The reason of the events the scanner publishes on violating resources.
+pub const POLICY_VIOLATION: &str = "PolicyViolation";
+
+/// The API server rejects longer Event notes.
+const MAX_NOTE_BYTES: usize = 1024;
+
+/// Publishes Kubernetes Events, dropping any event already published for the
+/// same object with the same reason and note within [`DEDUP_WINDOW`].
+#[derive(Clone)]
+pub struct EventRecorder {
+    client: Client,
+    recorder: Recorder,
+    recent: Arc<Mutex<HashMap<Key, Instant>>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Key {
+    object: (
+        Option<String>,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+    ),
+    reason: String,
+    note: String,
+}
+
+impl EventRecorder {
+    pub fn new(client: Client) -> Self {
+        let reporter = Reporter {
+            controller: "kube-guardian".to_string(),
+            instance: std::env::var("POD_NAME").ok(),
+        };
+        EventRecorder {
+            recorder: Recorder::new(client.clone(), reporter),
+            client,
+            recent: Arc::default(),
+        }
+    }
+
+    pub async fn normal(
+        &self,
+        regarding: &ObjectReference,
+        reason: &str,
+        action: &str,
+        note: &str,
+    ) {
+        self.publish(regarding, EventType::Normal, reason, action, note)
+            .await;
+    }
+
+    pub async fn warning(
+        &self,
+        regarding: &ObjectReference,
+        reason: &str,
+        action: &str,
+        note: &str,
+    ) {
+        self.publish(regarding, EventType::Warning, reason, action, note)
+            .await;
+    }
+
+    /// Deletes the events published about a cluster-scoped object, which the
+    /// recorder keeps in the `default` namespace.
+    pub async fn delete_cluster_events(&self, uid: &str) -> Result<(), kube::Error> {
+        self.recent
+            .lock()
+            .unwrap()
+            .retain(|key, _| key.object.3.as_deref() != Some(uid));
+        let api: Api<CoreEvent> = Api::namespaced(self.client.clone(), "default");
+        let params = ListParams::default().fields(&format!("involvedObject.uid={uid}"));
+        api.delete_collection(&DeleteParams::default(), &params)
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes the `PolicyViolation` events published about resources that
+    /// violate `policy`, in every namespace. Their notes start with the
+    /// policy name, as rule results are displayed.
+    pub async fn delete_violation_events(&self, policy: &str) -> Result<(), kube::Error> {
+        let prefix = violation_prefix(policy);
+        self.recent
+            .lock()
+            .unwrap()
+            .retain(|key, _| !(key.reason == POLICY_VIOLATION && key.note.starts_with(&prefix)));
+        let api: Api<CoreEvent> = Api::all(self.client.clone());
+        let params = ListParams::default().fields(&format!("reason={POLICY_VIOLATION}"));
+        for event in api.list(&params).await? {
+            if !event
+                .message
+                .as_deref()
+                .is_some_and(|note| note.starts_with(&prefix))
+            {
+                continue;
+            }
+            let (Some(namespace), Some(name)) = (&event.metadata.namespace, &event.metadata.name)
+            else {
+                continue;
+            };
+            let api: Api<CoreEvent> = Api::namespaced(self.client.clone(), namespace);
+            match api.delete(name, &DeleteParams::default()).await {
+                Ok(_) => {}
+                Err(kube::Error::Api(e)) if e.code == 404 => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Events are best effort: failures are logged, never returned.
+    async fn publish(
+        &self,
+        regarding: &ObjectReference,
+        type_: EventType,
+        reason: &str,
+        action: &str,
+        note: &str,
+    ) {
+        let note = truncate(note);
+        let key = Key {
+            object: (
+                regarding.kind.clone(),
+                regarding.namespace.clone(),
+                regarding.name.clone(),
+                regarding.uid.clone(),
+            ),
+            reason: reason.to_string(),
+            note: note.clone(),
+        };
+        {
+            let now = Instant::now();
+            let mut recent = self.recent.lock().unwrap();
+            recent.retain(|_, published| now.duration_since(*published) < DEDUP_WINDOW);
+            if recent.contains_key(&key) {
+                return;
+            }
+            recent.insert(key.clone(), now);
+        }
+
+        let event = Event {
+            type_,
+            reason: reason.to_string(),
+            note: Some(note),
+            action: action.to_string(),
+            secondary: None,
+        };
+        if let Err(e) = self.recorder.publish(&event, regarding).await {
+            // Let the next occurrence try again.
+            self.recent.lock().unwrap().remove(&key);
+            warn!(
+                "Cannot publish {reason} event for {}: {e}",
+                regarding.name.as_deref().unwrap_or_default()
+            );
+        }
+    }
+}
+
+/// The start of every violation note of `policy`, see `RuleResult`'s
+/// `Display`.
+fn violation_prefix(policy: &str) -> String {
+    format!("[{policy}/")
+}
+
+fn truncate(note: &str) -> String {
+    if note.len() <= MAX_NOTE_BYTES {
+        return note.to_string();
+    }
+    let mut end = MAX_NOTE_BYTES - '…'.len_utf8();
+    while !note.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &note[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crd::Severity;
+    use crate::governance::traits::{Outcome, RuleResult};
+
+    #[test]
+    fn violation_notes_start_with_the_policy_prefix() {
+        let result = RuleResult {
+            policy: "require-team".to_string(),
+            rule: "team-label".to_string(),
+            severity: Severity::High,
+            outcome: Outcome::Fail,
+            message: "label team is required".to_string(),
+            paths: Vec::new(),
+        };
+        let note = truncate(&result.to_string());
+        assert!(note.starts_with(&violation_prefix("require-team")));
+        assert!(!note.starts_with(&violation_prefix("require")));
+    }
+}
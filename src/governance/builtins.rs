@@ -0,0 +1,515 @@
+use serde_json::Value;
+
+use super::policies::FieldFailure;
+use super::workload;
+use crate::crd::BuiltinRule;
+
+/// Names of every built-in check, as referenced from `rules[].builtin.name`.
+pub const NAMES: &[&str] = &[
+    "disallow-privileged",
+    "require-non-root",
+    "disallow-host-path",
+    "disallow-host-network",
+    "disallow-host-pid",
+    "require-resource-limits",
+    "disallow-latest-tag",
+    "restrict-image-registries",
+    "require-read-only-root-filesystem",
+    "drop-all-capabilities",
+];
+
+/// A built-in security check on the pod spec of a workload. Objects without
+/// a pod spec always pass.
+pub enum Builtin {
+    DisallowPrivileged,
+    RequireNonRoot,
+    DisallowHostPath,
+    DisallowHostNetwork,
+    DisallowHostPid,
+    /// `resources` parameter; defaults to cpu and memory.
+    RequireResourceLimits {
+        resources: Vec<String>,
+    },
+    DisallowLatestTag,
+    /// `registries` parameter: allowed image reference prefixes, normalized
+    /// like the images they are compared with.
+    RestrictImageRegistries {
+        registries: Vec<String>,
+    },
+    RequireReadOnlyRootFilesystem,
+    DropAllCapabilities,
+}
+
+impl Builtin {
+    pub fn compile(rule: &BuiltinRule) -> Result<Builtin, String> {
+        let name = rule.name.as_str();
+        let accepted: &[&str] = match name {
+            "require-resource-limits" => &["resources"],
+            "restrict-image-registries" => &["registries"],
+            _ => &[],
+        };
+        if let Some(unknown) = rule
+            .params
+            .keys()
+            .find(|key| !accepted.contains(&key.as_str()))
+        {
+            return Err(format!("builtin {name} has no parameter {unknown:?}"));
+        }
+        let param = |key: &str| rule.params.get(key).cloned().unwrap_or_default();
+
+        Ok(match name {
+            "disallow-privileged" => Builtin::DisallowPrivileged,
+            "require-non-root" => Builtin::RequireNonRoot,
+            "disallow-host-path" => Builtin::DisallowHostPath,
+            "disallow-host-network" => Builtin::DisallowHostNetwork,
+            "disallow-host-pid" => Builtin::DisallowHostPid,
+            "require-resource-limits" => {
+                let mut resources = param("resources");
+                if resources.is_empty() {
+                    resources = vec!["cpu".to_string(), "memory".to_string()];
+                }
+                Builtin::RequireResourceLimits { resources }
+            }
+            "disallow-latest-tag" => Builtin::DisallowLatestTag,
+            "restrict-image-registries" => {
+                let registries = param("registries");
+                if registries.is_empty() {
+                    return Err(format!("builtin {name} requires the registries parameter"));
+                }
+                if registries
+                    .iter()
+                    .any(|prefix| prefix.trim_matches([' ', '/']).is_empty())
+                {
+                    return Err(format!("builtin {name} has an empty registry prefix"));
+                }
+                let registries = registries.iter().map(|p| normalize_prefix(p)).collect();
+                Builtin::RestrictImageRegistries { registries }
+            }
+            "require-read-only-root-filesystem" => Builtin::RequireReadOnlyRootFilesystem,
+            "drop-all-capabilities" => Builtin::DropAllCapabilities,
+            _ => {
+                return Err(format!(
+                    "unknown builtin {name:?}; expected one of {}",
+                    NAMES.join(", ")
+                ));
+            }
+        })
+    }
+
+    pub fn failures(&self, kind: &str, object: &Value) -> Vec<FieldFailure> {
+        let Some((spec_path, spec)) = workload::pod_spec(kind, object) else {
+            return Vec::new();
+        };
+        let mut failures = Vec::new();
+        let mut fail = |path: String, message: String| {
+            failures.push(FieldFailure {
+                message: format!("{path}: {message}"),
+                path,
+            })
+        };
+
+        match self {
+            Builtin::DisallowHostPath => {
+                for (index, volume) in spec["volumes"].as_array().into_iter().flatten().enumerate()
+                {
+                    if volume.get("hostPath").is_some() {
+                        fail(
+                            format!("{spec_path}.volumes[{index}].hostPath"),
+                            "hostPath volumes are not allowed".to_string(),
+                        );
+                    }
+                }
+            }
+            Builtin::DisallowHostNetwork => {
+                if spec["hostNetwork"] == true {
+                    fail(
+                        format!("{spec_path}.hostNetwork"),
+                        "sharing the host network is not allowed".to_string(),
+                    );
+                }
+            }
+            Builtin::DisallowHostPid => {
+                if spec["hostPID"] == true {
+                    fail(
+                        format!("{spec_path}.hostPID"),
+                        "sharing the host PID namespace is not allowed".to_string(),
+                    );
+                }
+            }
+            _ => {
+                for (path, container) in workload::containers(&spec_path, spec) {
+                    if let Some((field, message)) = self.container_failure(spec, &path, container) {
+                        fail(format!("{path}{field}"), message);
+                    }
+                }
+            }
+        }
+        failures
+    }
+
+    /// The failing field below the container and why, for container-level checks.
+    fn container_failure(
+        &self,
+        pod_spec: &Value,
+        path: &str,
+        container: &Value,
+    ) -> Option<(String, String)> {
+        let security = &container["securityContext"];
+        let image = container["image"].as_str().unwrap_or_default();
+        let ephemeral = path.contains(".ephemeralContainers[");
+
+        match self {
+            Builtin::DisallowPrivileged if security["privileged"] == true => Some((
+                ".securityContext.privileged".to_string(),
+                "privileged containers are not allowed".to_string(),
+            )),
+            Builtin::RequireNonRoot => {
+                // The container setting overrides the pod-level default.
+                let non_root = security["runAsNonRoot"]
+                    .as_bool()
+                    .or_else(|| pod_spec["securityContext"]["runAsNonRoot"].as_bool());
+                let user = security["runAsUser"]
+                    .as_i64()
+                    .or_else(|| pod_spec["securityContext"]["runAsUser"].as_i64());
+                if user == Some(0) {
+                    Some((
+                        ".securityContext.runAsUser".to_string(),
+                        "containers must not run as user 0".to_string(),
+                    ))
+                } else if non_root != Some(true) {
+                    Some((
+                        ".securityContext.runAsNonRoot".to_string(),
+                        "containers must set runAsNonRoot to true".to_string(),
+                    ))
+                } else {
+                    None
+                }
+            }
+            // Ephemeral containers cannot declare resources.
+            Builtin::RequireResourceLimits { resources } if !ephemeral => {
+                let missing: Vec<&str> = resources
+                    .iter()
+                    .filter(|resource| container["resources"]["limits"].get(resource).is_none())
+                    .map(String::as_str)
+                    .collect();
+                (!missing.is_empty()).then(|| {
+                    (
+                        ".resources.limits".to_string(),
+                        format!("containers must set {} limits", missing.join(" and ")),
+                    )
+                })
+            }
+            Builtin::DisallowLatestTag if uses_latest_tag(image) => Some((
+                ".image".to_string(),
+                format!("image {image:?} must be pinned to a tag other than latest"),
+            )),
+            Builtin::RestrictImageRegistries { registries } => {
+                let reference = normalize_image(image);
+                (!registries.iter().any(|prefix| allows(prefix, &reference))).then(|| {
+                    (
+                        ".image".to_string(),
+                        format!(
+                            "image {image:?} must come from one of {}",
+                            registries.join(", ")
+                        ),
+                    )
+                })
+            }
+            Builtin::RequireReadOnlyRootFilesystem
+                if security["readOnlyRootFilesystem"] != true =>
+            {
+                Some((
+                    ".securityContext.readOnlyRootFilesystem".to_string(),
+                    "containers must use a read-only root filesystem".to_string(),
+                ))
+            }
+            Builtin::DropAllCapabilities => {
+                let drops_all = security["capabilities"]["drop"]
+                    .as_array()
+                    .is_some_and(|drop| drop.iter().any(|cap| cap == "ALL"));
+                (!drops_all).then(|| {
+                    (
+                        ".securityContext.capabilities.drop".to_string(),
+                        "containers must drop ALL capabilities".to_string(),
+                    )
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Images without a tag resolve to `latest`; digest-pinned images are fine.
+fn uses_latest_tag(image: &str) -> bool {
+    if image.contains('@') {
+        return false;
+    }
+    let name = image.rsplit('/').next().unwrap_or(image);
+    match name.split_once(':') {
+        Some((_, tag)) => tag == "latest",
+        None => true,
+    }
+}
+
+/// Normalizes an allowed registry prefix. A prefix ending in `/` allows
+/// everything below that path; any other prefix names a registry host or an
+/// image, such as `docker.io/nginx` for `docker.io/library/nginx`.
+fn normalize_prefix(prefix: &str) -> String {
+    let prefix = prefix.trim();
+    let (path, below) = match prefix.strip_suffix('/') {
+        Some(path) => (path.trim_end_matches('/'), true),
+        None => (prefix, false),
+    };
+    let first = path.split('/').next().unwrap_or_default();
+    let host = first.contains('.') || first.contains(':') || first == "localhost";
+    match (below, host) {
+        (false, true) if !path.contains('/') => path.to_string(),
+        (false, _) => normalize_image(path),
+        (true, true) => format!("{path}/"),
+        (true, false) => format!("docker.io/{path}/"),
+    }
+}
+
+/// Whether `prefix` covers the normalized image `reference`. Unless the
+/// prefix ends in `/`, the match has to end at a path, port, tag or digest
+/// separator, so `registry.example.com` does not allow
+/// `registry.example.com.evil.io/app`.
+fn allows(prefix: &str, reference: &str) -> bool {
+    reference.strip_prefix(prefix).is_some_and(|rest| {
+        prefix.ends_with('/') || rest.is_empty() || rest.starts_with(['/', ':', '@'])
+    })
+}
+
+/// Spells out the registry and namespace Docker assumes, so `nginx`,
+/// `docker.io/nginx` and `docker.io/library/nginx` are compared alike.
+fn normalize_image(image: &str) -> String {
+    let Some((first, rest)) = image.split_once('/') else {
+        return format!("docker.io/library/{image}");
+    };
+    if first == "docker.io" && !rest.contains('/') {
+        format!("docker.io/library/{rest}")
+    } else if first.contains('.') || first.contains(':') || first == "localhost" {
+        image.to_string()
+    } else {
+        format!("docker.io/{image}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn builtin(name: &str, params: &[(&str, &[&str])]) -> Result<Builtin, String> {
+        Builtin::compile(&BuiltinRule {
+            name: name.to_string(),
+            params: params
+                .iter()
+                .map(|(key, values)| {
+                    let values = values.iter().map(|value| value.to_string()).collect();
+                    (key.to_string(), values)
+                })
+                .collect::<BTreeMap<_, _>>(),
+        })
+    }
+
+    fn paths(builtin: &Builtin, pod_spec: Value) -> Vec<String> {
+        builtin
+            .failures("Pod", &json!({ "spec": pod_spec }))
+            .into_iter()
+            .map(|failure| failure.path)
+            .collect()
+    }
+
+    #[test]
+    fn latest_tag_detection() {
+        for image in [
+            "nginx",
+            "nginx:latest",
+            "localhost:5000/nginx",
+            "localhost:5000/nginx:latest",
+            "registry.example.com/team/app",
+        ] {
+            assert!(uses_latest_tag(image), "{image}");
+        }
+        for image in [
+            "nginx:1.27",
+            "localhost:5000/nginx:1.27",
+            "nginx@sha256:0123abcd",
+            "nginx:latest@sha256:0123abcd",
+        ] {
+            assert!(!uses_latest_tag(image), "{image}");
+        }
+    }
+
+    #[test]
+    fn docker_hub_images_are_normalized() {
+        for image in [
+            "nginx",
+            "library/nginx",
+            "docker.io/nginx",
+            "docker.io/library/nginx",
+        ] {
+            assert_eq!(normalize_image(image), "docker.io/library/nginx", "{image}");
+        }
+        assert_eq!(normalize_image("bitnami/nginx"), "docker.io/bitnami/nginx");
+        assert_eq!(normalize_image("ghcr.io/org/app"), "ghcr.io/org/app");
+        assert_eq!(
+            normalize_image("localhost:5000/nginx"),
+            "localhost:5000/nginx"
+        );
+        assert_eq!(normalize_image("localhost/nginx"), "localhost/nginx");
+    }
+
+    #[test]
+    fn registries_are_compared_after_normalizing() {
+        let allowed = builtin(
+            "restrict-image-registries",
+            &[("registries", &["docker.io/library/"])],
+        )
+        .unwrap();
+        let spec = json!({ "containers": [
+            { "name": "a", "image": "docker.io/nginx:1.27" },
+            { "name": "b", "image": "quay.io/nginx:1.27" },
+        ]});
+        assert_eq!(paths(&allowed, spec), vec!["spec.containers[1].image"]);
+    }
+
+    #[test]
+    fn registry_prefixes_end_at_a_boundary() {
+        let allowed = builtin(
+            "restrict-image-registries",
+            &[("registries", &["registry.example.com", "docker.io/nginx"])],
+        )
+        .unwrap();
+        let spec = json!({ "containers": [
+            { "name": "a", "image": "registry.example.com/team/app:1.0" },
+            { "name": "b", "image": "registry.example.com:5000/app:1.0" },
+            { "name": "c", "image": "registry.example.com.evil.io/app:1.0" },
+            { "name": "d", "image": "registry.example.com-evil/app:1.0" },
+            { "name": "e", "image": "nginx:1.27" },
+            { "name": "f", "image": "nginx@sha256:0123abcd" },
+            { "name": "g", "image": "nginx-evil:1.27" },
+            { "name": "h", "image": "docker.io/library/nginx" },
+        ]});
+        assert_eq!(
+            paths(&allowed, spec),
+            vec![
+                "spec.containers[2].image",
+                "spec.containers[3].image",
+                "spec.containers[6].image"
+            ]
+        );
+    }
+
+    #[test]
+    fn registry_prefixes_are_normalized() {
+        for (prefix, normalized) in [
+            ("registry.example.com", "registry.example.com"),
+            ("registry.example.com/", "registry.example.com/"),
+            ("localhost:5000", "localhost:5000"),
+            ("ghcr.io/acme/", "ghcr.io/acme/"),
+            ("ghcr.io/acme", "ghcr.io/acme"),
+            ("nginx", "docker.io/library/nginx"),
+            ("docker.io/nginx", "docker.io/library/nginx"),
+            ("docker.io/library/", "docker.io/library/"),
+            ("bitnami/", "docker.io/bitnami/"),
+            (" docker.io// ", "docker.io/"),
+        ] {
+            assert_eq!(normalize_prefix(prefix), normalized, "{prefix}");
+        }
+    }
+
+    #[test]
+    fn parameters_are_validated() {
+        assert_eq!(
+            builtin("disallow-privileged", &[("resources", &["cpu"])])
+                .err()
+                .unwrap(),
+            "builtin disallow-privileged has no parameter \"resources\""
+        );
+        assert_eq!(
+            builtin("restrict-image-registries", &[]).err().unwrap(),
+            "builtin restrict-image-registries requires the registries parameter"
+        );
+        assert_eq!(
+            builtin(
+                "restrict-image-registries",
+                &[("registries", &["ghcr.io/", "/"])]
+            )
+            .err()
+            .unwrap(),
+            "builtin restrict-image-registries has an empty registry prefix"
+        );
+        assert!(
+            builtin("disallow-everything", &[])
+                .err()
+                .unwrap()
+                .starts_with("unknown builtin \"disallow-everything\"")
+        );
+        for name in NAMES {
+            let params: &[(&str, &[&str])] = if *name == "restrict-image-registries" {
+                &[("registries", &["ghcr.io/"])]
+            } else {
+                &[]
+            };
+            assert!(builtin(name, params).is_ok(), "{name}");
+        }
+    }
+
+    #[test]
+    fn resource_limits_default_to_cpu_and_memory() {
+        let limits = builtin("require-resource-limits", &[]).unwrap();
+        let spec = json!({ "containers": [
+            { "name": "a", "resources": { "limits": { "cpu": "1", "memory": "1Gi" } } },
+            { "name": "b", "resources": { "limits": { "cpu": "1" } } },
+        ]});
+        let failures = limits.failures("Pod", &json!({ "spec": spec }));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(
+            failures[0].message,
+            "spec.containers[1].resources.limits: containers must set memory limits"
+        );
+
+        let gpus = builtin(
+            "require-resource-limits",
+            &[("resources", &["nvidia.com/gpu"])],
+        )
+        .unwrap();
+        let spec =
+            json!({ "containers": [{ "name": "a", "resources": { "limits": { "cpu": "1" } } }]});
+        assert_eq!(
+            paths(&gpus, spec),
+            vec!["spec.containers[0].resources.limits"]
+        );
+    }
+
+    #[test]
+    fn ephemeral_containers_are_exempt_from_resource_limits() {
+        let limits = builtin("require-resource-limits", &[]).unwrap();
+        let spec = json!({
+            "containers": [{ "name": "a", "resources": { "limits": { "cpu": "1", "memory": "1Gi" } } }],
+            "ephemeralContainers": [{ "name": "debug", "image": "busybox:1.36" }],
+        });
+        assert!(paths(&limits, spec.clone()).is_empty());
+
+        // Other checks still cover them.
+        let latest = builtin("disallow-latest-tag", &[]).unwrap();
+        let spec = json!({ "ephemeralContainers": [{ "name": "debug", "image": "busybox" }] });
+        assert_eq!(
+            paths(&latest, spec),
+            vec!["spec.ephemeralContainers[0].image"]
+        );
+    }
+
+    #[test]
+    fn objects_without_a_pod_spec_pass() {
+        let privileged = builtin("disallow-privileged", &[]).unwrap();
+        assert!(
+            privileged
+                .failures("ConfigMap", &json!({ "data": {} }))
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use kube::ResourceExt;
+use kube::runtime::reflector::Store;
+use kube::runtime::watcher;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use super::cel::CompiledPolicy;
+use super::policies::Policy;
+use crate::crd::GuardianPolicy;
+
+/// Compiled policies shared by the reconciler, the scanner and the webhook. A
+/// policy is compiled the first time it is seen and again only when its spec
+/// changes, and forgotten when a policy watch sees it deleted.
+#[derive(Clone, Default)]
+pub struct PolicyCache {
+    entries: Arc<RwLock<HashMap<String, Entry>>>,
+}
+
+struct Entry {
+    version: Version,
+    compiled: Arc<CompiledPolicy>,
+}
+
+/// What identifies one compilation: the object, its spec generation, and
+/// whether it is being deleted (which does not always bump the generation).
+#[derive(PartialEq)]
+struct Version {
+    uid: Option<String>,
+    generation: Option<i64>,
+    deleting: bool,
+}
+
+impl PolicyCache {
+    pub fn get(&self, policy: &GuardianPolicy) -> Arc<CompiledPolicy> {
+        let name = policy.name_any();
+        let version = Version::of(policy);
+        if let Some(entry) = self.entries.read().unwrap().get(&name)
+            && entry.version == version
+        {
+            return entry.compiled.clone();
+        }
+
+        let compiled = Arc::new(CompiledPolicy::compile(Policy::from(policy)));
+        self.entries.write().unwrap().insert(
+            name,
+            Entry {
+                version,
+                compiled: compiled.clone(),
+            },
+        );
+        compiled
+    }
+
+    /// Compiles every policy in `policies`. Only policies seen for the first
+    /// time or with a changed spec take the write lock.
+    pub fn compile_all(&self, policies: &[Arc<GuardianPolicy>]) -> Vec<Arc<CompiledPolicy>> {
+        policies.iter().map(|policy| self.get(policy)).collect()
+    }
+
+    /// Forgets the policies a watch `event` shows are gone from `store`: a
+    /// deleted policy, or after a re-list every policy the list left out.
+    pub fn prune(&self, event: &watcher::Event<GuardianPolicy>, store: &Store<GuardianPolicy>) {
+        match event {
+            watcher::Event::Delete(policy) => {
+                self.entries.write().unwrap().remove(&policy.name_any());
+            }
+            watcher::Event::InitDone => {
+                let names: HashSet<String> = store
+                    .state()
+                    .iter()
+                    .map(|policy| policy.name_any())
+                    .collect();
+                self.entries
+                    .write()
+                    .unwrap()
+                    .retain(|name, _| names.contains(name));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Version {
+    fn of(policy: &GuardianPolicy) -> Version {
+        Version {
+            uid: policy.uid(),
+            generation: policy.metadata.generation,
+            deleting: policy.metadata.deletion_timestamp.is_some(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kube::runtime::reflector;
+    use serde_json::json;
+
+    fn policy(name: &str, generation: i64) -> GuardianPolicy {
+        serde_json::from_value(json!({
+            "apiVersion": "guardian.io/v1",
+            "kind": "GuardianPolicy",
+            "metadata": { "name": name, "uid": name, "generation": generation },
+            "spec": { "severity": "Low", "rules": [] },
+        }))
+        .unwrap()
+    }
+
+    fn cached(cache: &PolicyCache) -> Vec<String> {
+        let mut names: Vec<_> = cache.entries.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn policies_are_compiled_once_per_generation() {
+        let cache = PolicyCache::default();
+        let first = cache.get(&policy("a", 1));
+        assert!(Arc::ptr_eq(&first, &cache.get(&policy("a", 1))));
+        assert!(!Arc::ptr_eq(&first, &cache.get(&policy("a", 2))));
+    }
+
+    #[test]
+    fn compiling_does_not_forget_other_policies() {
+        let cache = PolicyCache::default();
+        cache.compile_all(&[Arc::new(policy("a", 1))]);
+        cache.compile_all(&[Arc::new(policy("b", 1))]);
+        assert_eq!(cached(&cache), ["a", "b"]);
+    }
+
+    #[test]
+    fn deleted_policies_are_forgotten() {
+        let cache = PolicyCache::default();
+        let (store, mut writer) = reflector::store();
+        for name in ["a", "b"] {
+            let event = watcher::Event::Apply(policy(name, 1));
+            writer.apply_watcher_event(&event);
+            cache.prune(&event, &store);
+            cache.compile_all(&store.state());
+        }
+        assert_eq!(cached(&cache), ["a", "b"]);
+
+        let event = watcher::Event::Delete(policy("a", 1));
+        writer.apply_watcher_event(&event);
+        cache.prune(&event, &store);
+        assert_eq!(cached(&cache), ["b"]);
+    }
+
+    #[test]
+    fn relists_forget_policies_deleted_meanwhile() {
+        let cache = PolicyCache::default();
+        cache.compile_all(&[Arc::new(policy("a", 1)), Arc::new(policy("b", 1))]);
+
+        let (store, mut writer) = reflector::store();
+        let events = [
+            watcher::Event::Init,
+            watcher::Event::InitApply(policy("b", 1)),
+            watcher::Event::InitDone,
+        ];
+        for event in events {
+            writer.apply_watcher_event(&event);
+            cache.prune(&event, &store);
+        }
+        assert_eq!(cached(&cache), ["b"]);
+    }
+}
@@ -0,0 +1,261 @@
+use cel_interpreter::{Context, Program};
+use serde_json::Value;
+use std::cell::OnceCell;
+
+use super::builtins::Builtin;
+use super::pod_security;
+use super::policies::{FieldFailure, Pattern, Policy};
+use super::traits::{EvaluationContext, EvaluationResult, Outcome, PolicyEvaluator, RuleResult};
+use crate::crd::PolicyRule;
+
+/// A policy whose rule conditions have been compiled as CEL expressions and
+/// whose patterns and built-in checks have been parsed, so admission requests
+/// only pay for execution.
+pub struct CompiledPolicy {
+    pub policy: Policy,
+    /// One entry per rule, in rule order.
+    rules: Vec<CompiledRule>,
+    /// Why the policy cannot be enforced, as the status condition reason
+    /// and message.
+    validation: Result<(), (&'static str, String)>,
+}
+
+struct CompiledRule {
+    /// `None` for rules without a condition.
+    condition: Option<Result<Program, String>>,
+    patterns: Result<Vec<Pattern>, String>,
+    builtin: Option<Result<Builtin, String>>,
+}
+
+impl CompiledPolicy {
+    pub fn compile(policy: Policy) -> Self {
+        let rules = policy
+            .rules
+            .iter()
+            .map(|rule| CompiledRule {
+                condition: rule
+                    .condition
+                    .as_deref()
+                    .map(|condition| Program::compile(condition).map_err(|e| e.to_string())),
+                patterns: rule.patterns.iter().map(Pattern::compile).collect(),
+                builtin: rule.builtin.as_ref().map(Builtin::compile),
+            })
+            .collect();
+        let mut compiled = CompiledPolicy {
+            policy,
+            rules,
+            validation: Ok(()),
+        };
+        compiled.validation = compiled
+            .policy
+            .validate()
+            .map_err(|e| ("InvalidSpec", e))
+            .and_then(|()| compiled.check().map_err(|e| ("CompileError", e)));
+        compiled
+    }
+
+    /// The spec validation and compilation outcome reported in the policy's
+    /// status. Invalid policies are not enforced.
+    pub fn validation(&self) -> &Result<(), (&'static str, String)> {
+        &self.validation
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.validation.is_ok()
+    }
+
+    /// Fails with one message per rule whose condition, patterns or built-in
+    /// check do not compile.
+    fn check(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+        for (index, (rule, compiled)) in self.policy.rules.iter().zip(&self.rules).enumerate() {
+            if let Some(Err(e)) = &compiled.condition {
+                problems.push(format!(
+                    "rules[{index}] ({}): condition does not compile: {e}",
+                    rule.name
+                ));
+            }
+            if let Err(e) = &compiled.patterns {
+                problems.push(format!("rules[{index}] ({}): {e}", rule.name));
+            }
+            if let Some(Err(e)) = &compiled.builtin {
+                problems.push(format!("rules[{index}] ({}): {e}", rule.name));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("; "))
+        }
+    }
+
+    fn execute(&self, program: &Program, variables: &Context) -> Result<bool, String> {
+        match program.execute(variables) {
+            Ok(cel_interpreter::Value::Bool(holds)) => Ok(holds),
+            Ok(other) => Err(format!(
+                "condition must evaluate to a bool, got {:?}",
+                other.type_of()
+            )),
+            Err(e) => Err(format!("condition failed to evaluate: {e}")),
+        }
+    }
+
+    fn result(
+        &self,
+        rule: &PolicyRule,
+        outcome: Outcome,
+        message: String,
+        paths: Vec<String>,
+    ) -> RuleResult {
+        RuleResult {
+            policy: self.policy.name.clone(),
+            rule: rule.name.clone(),
+            severity: self.policy.severity,
+            outcome,
+            message,
+            paths,
+        }
+    }
+
+    fn evaluate_rule(
+        &self,
+        rule: &PolicyRule,
+        compiled: &CompiledRule,
+        object: &Value,
+        context: &EvaluationContext,
+        variables: &OnceCell<Result<Context<'static>, String>>,
+    ) -> RuleResult {
+        let result = |outcome, message, paths| self.result(rule, outcome, message, paths);
+
+        let patterns = match &compiled.patterns {
+            Ok(patterns) => patterns,
+            Err(e) => return result(Outcome::Error, e.clone(), Vec::new()),
+        };
+        let builtin = match &compiled.builtin {
+            Some(Err(e)) => return result(Outcome::Error, e.clone(), Vec::new()),
+            Some(Ok(builtin)) => Some(builtin),
+            None => None,
+        };
+        let holds = match &compiled.condition {
+            None => true,
+            Some(Err(e)) => {
+                let message = format!("condition does not compile: {e}");
+                return result(Outcome::Error, message, Vec::new());
+            }
+            Some(Ok(program)) => match variables
+                .get_or_init(|| variables_for(object, context))
+                .as_ref()
+                .map_err(Clone::clone)
+                .and_then(|variables| self.execute(program, variables))
+            {
+                Ok(holds) => holds,
+                Err(e) => return result(Outcome::Error, e, Vec::new()),
+            },
+        };
+        if compiled.condition.is_none()
+            && patterns.is_empty()
+            && builtin.is_none()
+            && rule.pod_security.is_none()
+        {
+            return result(Outcome::Skip, "rule only sets defaults".into(), Vec::new());
+        }
+
+        let mut failures: Vec<_> = patterns
+            .iter()
+            .flat_map(|pattern| pattern.failures(object))
+            .collect();
+        if let Some(builtin) = builtin {
+            failures.extend(builtin.failures(&context.target.kind, object));
+        }
+        if let Some(profile) = &rule.pod_security {
+            let violations = pod_security::violations(profile.level, &context.target.kind, object);
+            if !violations.is_empty() {
+                // The whole profile reports one message, worded like PodSecurity admission.
+                let message = pod_security::summary(profile.level, &violations);
+                failures.extend(
+                    violations
+                        .into_iter()
+                        .flat_map(|violation| violation.paths)
+                        .map(|path| FieldFailure {
+                            path,
+                            message: message.clone(),
+                        }),
+                );
+            }
+        }
+        if holds && failures.is_empty() {
+            return result(Outcome::Pass, String::new(), Vec::new());
+        }
+        // Rules without a condition may leave the message out and report what failed.
+        let message = if rule.message.trim().is_empty() {
+            let mut messages: Vec<&str> = Vec::new();
+            for failure in &failures {
+                if !messages.contains(&failure.message.as_str()) {
+                    messages.push(&failure.message);
+                }
+            }
+            messages.join("; ")
+        } else {
+            rule.message.clone()
+        };
+        let mut paths = Vec::new();
+        for failure in failures {
+            if !paths.contains(&failure.path) {
+                paths.push(failure.path);
+            }
+        }
+        result(Outcome::Fail, message, paths)
+    }
+}
+
+impl PolicyEvaluator for CompiledPolicy {
+    /// Conditions see the same variables as a ValidatingAdmissionPolicy:
+    /// `object`, `oldObject`, `request` and `namespaceObject`, with `null`
+    /// standing in for whatever the caller does not have.
+    fn evaluate(&self, object: &Value, context: &EvaluationContext) -> EvaluationResult {
+        // Only built once a selected rule has a condition to run.
+        let variables = OnceCell::new();
+
+        let rules = self
+            .policy
+            .rules
+            .iter()
+            .zip(&self.rules)
+            .map(|(rule, compiled)| {
+                let skip =
+                    |reason: &str| self.result(rule, Outcome::Skip, reason.to_string(), Vec::new());
+                if !self.policy.enabled {
+                    skip("policy is being deleted")
+                } else if !rule.match_resources.matches(context.target) {
+                    skip("rule does not select this object")
+                } else {
+                    self.evaluate_rule(rule, compiled, object, context, &variables)
+                }
+            })
+            .collect();
+
+        EvaluationResult {
+            policy: self.policy.name.clone(),
+            enforcement_action: self.policy.enforcement_action,
+            failure_policy: self.policy.failure_policy,
+            rules,
+        }
+    }
+}
+
+fn variables_for(object: &Value, context: &EvaluationContext) -> Result<Context<'static>, String> {
+    let mut variables = Context::default();
+    let bindings = [
+        ("object", Some(object)),
+        ("oldObject", context.old_object),
+        ("request", context.request),
+        ("namespaceObject", context.namespace_object),
+    ];
+    for (name, value) in bindings {
+        let value = cel_interpreter::to_value(value.unwrap_or(&Value::Null))
+            .map_err(|e| format!("cannot expose {name} to CEL: {e}"))?;
+        variables.add_variable_from_value(name, value);
+    }
+    Ok(variables)
+}
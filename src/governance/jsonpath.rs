@@ -0,0 +1,288 @@
+use serde_json::Value;
+use std::fmt;
+
+/// The JSONPath subset pattern rules address fields with: dotted field
+/// names, `['quoted.keys']`, array indexes and `[*]`/`*` wildcards, with an
+/// optional leading `$`. `spec.containers[*].image` selects every container
+/// image.
+#[derive(Clone, Debug)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Field(String),
+    Index(usize),
+    Wildcard,
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<JsonPath, String> {
+        let invalid = |reason: String| format!("invalid path {path:?}: {reason}");
+        let trimmed = path.trim();
+        let trimmed = trimmed.strip_prefix('$').unwrap_or(trimmed);
+        // A leading dot is optional so `$.spec` and `spec` are the same path.
+        let mut rest = trimmed.strip_prefix('.').unwrap_or(trimmed);
+        let mut segments = Vec::new();
+
+        while !rest.is_empty() {
+            let segment = if let Some(bracketed) = rest.strip_prefix('[') {
+                let end = bracketed
+                    .find(']')
+                    .ok_or_else(|| invalid("unterminated [".to_string()))?;
+                let inner = bracketed[..end].trim();
+                rest = &bracketed[end + 1..];
+                if inner == "*" {
+                    Segment::Wildcard
+                } else if let Some(key) = quoted(inner) {
+                    Segment::Field(key.to_string())
+                } else if let Ok(index) = inner.parse() {
+                    Segment::Index(index)
+                } else {
+                    return Err(invalid(format!("unsupported selector [{inner}]")));
+                }
+            } else {
+                let end = rest.find(['.', '[']).unwrap_or(rest.len());
+                let field = &rest[..end];
+                rest = &rest[end..];
+                match field {
+                    "" => return Err(invalid("empty field name".to_string())),
+                    "*" => Segment::Wildcard,
+                    field => Segment::Field(field.to_string()),
+                }
+            };
+            segments.push(segment);
+
+            if let Some(after_dot) = rest.strip_prefix('.') {
+                if after_dot.is_empty() || after_dot.starts_with(['.', '[']) {
+                    return Err(invalid("empty field name".to_string()));
+                }
+                rest = after_dot;
+            }
+        }
+
+        if segments.is_empty() {
+            return Err(invalid("path is empty".to_string()));
+        }
+        Ok(JsonPath { segments })
+    }
+
+    /// Every location the path selects in `value`, written out with concrete
+    /// indexes and keys. A location that does not exist is returned with
+    /// `None`, its unresolved remainder written as in the path. Wildcards over
+    /// empty arrays or objects select nothing.
+    pub fn select<'v>(&self, value: &'v Value) -> Vec<(String, Option<&'v Value>)> {
+        let mut found = Vec::new();
+        select(&self.segments, value, String::new(), &mut found);
+        found
+    }
+}
+
+fn select<'v>(
+    segments: &[Segment],
+    value: &'v Value,
+    path: String,
+    found: &mut Vec<(String, Option<&'v Value>)>,
+) {
+    let Some((segment, rest)) = segments.split_first() else {
+        found.push((path, Some(value)));
+        return;
+    };
+    match (segment, value) {
+        (Segment::Field(key), Value::Object(map)) if map.contains_key(key) => {
+            select(rest, &map[key], append(path, segment), found);
+        }
+        (Segment::Index(index), Value::Array(items)) if *index < items.len() => {
+            select(rest, &items[*index], append(path, segment), found);
+        }
+        (Segment::Wildcard, Value::Array(items)) => {
+            for (index, item) in items.iter().enumerate() {
+                select(
+                    rest,
+                    item,
+                    append(path.clone(), &Segment::Index(index)),
+                    found,
+                );
+            }
+        }
+        (Segment::Wildcard, Value::Object(map)) => {
+            for (key, item) in map {
+                let field = Segment::Field(key.clone());
+                select(rest, item, append(path.clone(), &field), found);
+            }
+        }
+        _ => found.push((segments.iter().fold(path, append), None)),
+    }
+}
+
+fn append(path: String, segment: &Segment) -> String {
+    match segment {
+        Segment::Field(key) => {
+            let plain = key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            match (path.is_empty(), plain && !key.is_empty()) {
+                (true, true) => key.clone(),
+                (false, true) => format!("{path}.{key}"),
+                (_, false) => format!("{path}['{key}']"),
+            }
+        }
+        Segment::Index(index) => format!("{path}[{index}]"),
+        Segment::Wildcard => format!("{path}[*]"),
+    }
+}
+
+fn quoted(inner: &str) -> Option<&str> {
+    inner
+        .strip_prefix('\'')
+        .and_then(|s| s.strip_suffix('\''))
+        .or_else(|| inner.strip_prefix('"').and_then(|s| s.strip_suffix('"')))
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.segments.iter().fold(String::new(), append))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pod() -> Value {
+        json!({
+            "metadata": {
+                "annotations": { "app.kubernetes.io/name": "web" },
+            },
+            "spec": {
+                "containers": [
+                    { "name": "app", "image": "nginx:1.27" },
+                    { "name": "sidecar", "image": "envoy:1.31" },
+                ],
+                "volumes": [],
+                "hostname": "web-0",
+            },
+        })
+    }
+
+    fn select(path: &str, value: &Value) -> Vec<(String, Option<Value>)> {
+        JsonPath::parse(path)
+            .unwrap()
+            .select(value)
+            .into_iter()
+            .map(|(path, value)| (path, value.cloned()))
+            .collect()
+    }
+
+    #[test]
+    fn dollar_and_leading_dot_are_optional() {
+        for path in ["$.spec.hostname", ".spec.hostname", "spec.hostname"] {
+            assert_eq!(JsonPath::parse(path).unwrap().to_string(), "spec.hostname");
+            assert_eq!(
+                select(path, &pod()),
+                vec![("spec.hostname".to_string(), Some(json!("web-0")))]
+            );
+        }
+    }
+
+    #[test]
+    fn quoted_keys_may_contain_dots_and_slashes() {
+        for path in [
+            "metadata.annotations['app.kubernetes.io/name']",
+            "metadata.annotations[\"app.kubernetes.io/name\"]",
+        ] {
+            assert_eq!(
+                select(path, &pod()),
+                vec![(
+                    "metadata.annotations['app.kubernetes.io/name']".to_string(),
+                    Some(json!("web"))
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn indexes_select_one_item() {
+        assert_eq!(
+            select("spec.containers[1].image", &pod()),
+            vec![(
+                "spec.containers[1].image".to_string(),
+                Some(json!("envoy:1.31"))
+            )]
+        );
+        assert_eq!(
+            select("spec.containers[2].image", &pod()),
+            vec![("spec.containers[2].image".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn wildcards_expand_to_concrete_paths() {
+        assert_eq!(
+            select("spec.containers[*].image", &pod()),
+            vec![
+                (
+                    "spec.containers[0].image".to_string(),
+                    Some(json!("nginx:1.27"))
+                ),
+                (
+                    "spec.containers[1].image".to_string(),
+                    Some(json!("envoy:1.31"))
+                ),
+            ]
+        );
+        assert_eq!(
+            select("metadata.annotations.*", &pod()),
+            vec![(
+                "metadata.annotations['app.kubernetes.io/name']".to_string(),
+                Some(json!("web"))
+            )]
+        );
+    }
+
+    #[test]
+    fn wildcard_over_an_empty_array_selects_nothing() {
+        assert!(select("spec.volumes[*].name", &pod()).is_empty());
+    }
+
+    #[test]
+    fn wildcard_over_a_missing_field_is_missing() {
+        assert_eq!(
+            select("spec.initContainers[*].image", &pod()),
+            vec![("spec.initContainers[*].image".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn wildcard_over_a_scalar_is_missing() {
+        assert_eq!(
+            select("spec.hostname[*]", &pod()),
+            vec![("spec.hostname[*]".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn missing_fields_keep_the_unresolved_remainder() {
+        assert_eq!(
+            select("spec.securityContext.runAsNonRoot", &pod()),
+            vec![("spec.securityContext.runAsNonRoot".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        for path in ["", "$", "a..b", "a[", "a.", "a.[0]", "a[foo]", "a[-1]"] {
+            assert!(JsonPath::parse(path).is_err(), "{path:?} should not parse");
+        }
+        assert_eq!(
+            JsonPath::parse("a[").unwrap_err(),
+            "invalid path \"a[\": unterminated ["
+        );
+        assert_eq!(
+            JsonPath::parse("a..b").unwrap_err(),
+            "invalid path \"a..b\": empty field name"
+        );
+    }
+}
@@ -1,2 +1,9 @@
+pub mod builtins;
+pub mod cache;
+pub mod cel;
+pub mod jsonpath;
+pub mod mutation;
+pub mod pod_security;
 pub mod policies;
 pub mod traits;
+pub mod workload;
@@ -0,0 +1,239 @@
+use serde_json::{Map, Value};
+
+use super::workload::{containers_mut, pod_spec_mut};
+use crate::crd::ResourceDefaults;
+
+/// Fills in every default from `defaults` that `object` does not already set.
+pub fn apply_defaults(defaults: &ResourceDefaults, kind: &str, object: &mut Value) {
+    if !defaults.labels.is_empty()
+        && let Some(object) = object.as_object_mut()
+        && let Some(labels) =
+            child_object(object, "metadata").and_then(|metadata| child_object(metadata, "labels"))
+    {
+        for (key, value) in &defaults.labels {
+            labels
+                .entry(key.clone())
+                .or_insert_with(|| Value::String(value.clone()));
+        }
+    }
+
+    let Some(pod_spec) = pod_spec_mut(kind, object) else {
+        return;
+    };
+
+    if let Some(run_as_non_root) = defaults.run_as_non_root
+        && let Some(security) = pod_spec
+            .as_object_mut()
+            .and_then(|pod_spec| child_object(pod_spec, "securityContext"))
+    {
+        security
+            .entry("runAsNonRoot")
+            .or_insert(Value::Bool(run_as_non_root));
+    }
+
+    for container in containers_mut(pod_spec) {
+        let Some(container) = container.as_object_mut() else {
+            continue;
+        };
+        if let Some(policy) = defaults.image_pull_policy {
+            container
+                .entry("imagePullPolicy")
+                .or_insert_with(|| serde_json::to_value(policy).unwrap_or_default());
+        }
+        // Resources with a limit are left alone: the API server defaults their
+        // request to the limit, and a larger default would make the pod invalid.
+        let limits = container
+            .get("resources")
+            .and_then(|resources| resources.get("limits"));
+        let requests: Vec<_> = defaults
+            .resource_requests
+            .iter()
+            .filter(|(resource, _)| limits.and_then(|limits| limits.get(*resource)).is_none())
+            .map(|(resource, quantity)| (resource.clone(), Value::String(quantity.clone())))
+            .collect();
+        if !requests.is_empty()
+            && let Some(existing) = child_object(container, "resources")
+                .and_then(|resources| child_object(resources, "requests"))
+        {
+            for (resource, quantity) in requests {
+                existing.entry(resource).or_insert(quantity);
+            }
+        }
+    }
+}
+
+/// Returns `parent[field]` as an object, replacing a missing or null value
+/// with an empty one. Any other value is left alone and yields `None`.
+fn child_object<'a>(
+    parent: &'a mut Map<String, Value>,
+    field: &str,
+) -> Option<&'a mut Map<String, Value>> {
+    let value = parent.entry(field).or_insert(Value::Null);
+    if value.is_null() {
+        *value = Value::Object(Map::new());
+    }
+    value.as_object_mut()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn defaults() -> ResourceDefaults {
+        serde_json::from_value(json!({
+            "labels": { "owner": "platform" },
+            "resourceRequests": { "cpu": "100m", "memory": "128Mi" },
+            "runAsNonRoot": true,
+            "imagePullPolicy": "IfNotPresent",
+        }))
+        .unwrap()
+    }
+
+    fn pod(spec: Value) -> Value {
+        json!({ "metadata": { "name": "web" }, "spec": spec })
+    }
+
+    fn defaulted(kind: &str, mut object: Value) -> Value {
+        apply_defaults(&defaults(), kind, &mut object);
+        object
+    }
+
+    #[test]
+    fn fills_in_every_default() {
+        let object = defaulted(
+            "Pod",
+            pod(json!({ "containers": [{ "name": "app", "image": "nginx:1.27" }] })),
+        );
+        assert_eq!(
+            object,
+            json!({
+                "metadata": { "name": "web", "labels": { "owner": "platform" } },
+                "spec": {
+                    "securityContext": { "runAsNonRoot": true },
+                    "containers": [{
+                        "name": "app",
+                        "image": "nginx:1.27",
+                        "imagePullPolicy": "IfNotPresent",
+                        "resources": { "requests": { "cpu": "100m", "memory": "128Mi" } },
+                    }],
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_values_the_object_sets() {
+        let mut object = pod(json!({
+            "securityContext": { "runAsNonRoot": false },
+            "containers": [{
+                "name": "app",
+                "imagePullPolicy": "Always",
+                "resources": { "requests": { "cpu": "2" } },
+            }],
+        }));
+        object["metadata"]["labels"] = json!({ "owner": "payments", "app": "web" });
+        let object = defaulted("Pod", object);
+
+        assert_eq!(
+            object["metadata"]["labels"],
+            json!({ "owner": "payments", "app": "web" })
+        );
+        assert_eq!(object["spec"]["securityContext"]["runAsNonRoot"], false);
+        let container = &object["spec"]["containers"][0];
+        assert_eq!(container["imagePullPolicy"], "Always");
+        assert_eq!(
+            container["resources"]["requests"],
+            json!({ "cpu": "2", "memory": "128Mi" })
+        );
+    }
+
+    #[test]
+    fn limited_resources_get_no_default_request() {
+        let object = defaulted(
+            "Pod",
+            pod(json!({ "containers": [
+                { "name": "app", "resources": { "limits": { "cpu": "50m" } } },
+                { "name": "sidecar", "resources": { "limits": { "cpu": "1", "memory": "1Gi" } } },
+            ]})),
+        );
+        let containers = &object["spec"]["containers"];
+        assert_eq!(
+            containers[0]["resources"],
+            json!({ "limits": { "cpu": "50m" }, "requests": { "memory": "128Mi" } })
+        );
+        assert_eq!(
+            containers[1]["resources"],
+            json!({ "limits": { "cpu": "1", "memory": "1Gi" } })
+        );
+    }
+
+    #[test]
+    fn init_containers_get_defaults_but_ephemeral_containers_do_not() {
+        let object = defaulted(
+            "Pod",
+            pod(json!({
+                "initContainers": [{ "name": "migrate" }],
+                "containers": [{ "name": "app" }],
+                "ephemeralContainers": [{ "name": "debug" }],
+            })),
+        );
+        let spec = &object["spec"];
+        assert_eq!(spec["initContainers"][0]["imagePullPolicy"], "IfNotPresent");
+        assert_eq!(
+            spec["initContainers"][0]["resources"]["requests"]["cpu"],
+            "100m"
+        );
+        assert_eq!(spec["ephemeralContainers"][0], json!({ "name": "debug" }));
+    }
+
+    #[test]
+    fn workload_templates_are_defaulted() {
+        let deployment = json!({
+            "metadata": { "name": "web" },
+            "spec": { "template": { "spec": { "containers": [{ "name": "app" }] } } },
+        });
+        let object = defaulted("Deployment", deployment);
+        assert_eq!(object["metadata"]["labels"]["owner"], "platform");
+        let spec = &object["spec"]["template"]["spec"];
+        assert_eq!(spec["securityContext"]["runAsNonRoot"], true);
+        assert_eq!(
+            spec["containers"][0]["resources"]["requests"]["cpu"],
+            "100m"
+        );
+    }
+
+    #[test]
+    fn other_kinds_only_get_labels() {
+        let config_map = json!({ "metadata": { "name": "settings" }, "data": {} });
+        assert_eq!(
+            defaulted("ConfigMap", config_map),
+            json!({
+                "metadata": { "name": "settings", "labels": { "owner": "platform" } },
+                "data": {},
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_fields_are_not_replaced() {
+        let mut object = pod(json!({
+            "securityContext": "invalid",
+            "containers": [{ "name": "app", "resources": ["invalid"] }],
+        }));
+        object["metadata"]["labels"] = json!("invalid");
+        let before = object.clone();
+        let object = defaulted("Pod", object);
+
+        assert_eq!(object["metadata"], before["metadata"]);
+        assert_eq!(object["spec"]["securityContext"], "invalid");
+        assert_eq!(
+            object["spec"]["containers"][0]["resources"],
+            json!(["invalid"])
+        );
+
+        // Null counts as unset.
+        let object = defaulted("Pod", json!({ "metadata": { "labels": null } }));
+        assert_eq!(object["metadata"]["labels"], json!({ "owner": "platform" }));
+    }
+}
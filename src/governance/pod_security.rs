@@ -0,0 +1,1187 @@
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::workload;
+use crate::crd::PodSecurityLevel;
+
+/// Capabilities the baseline profile allows containers to add.
+const BASELINE_CAPABILITIES: &[&str] = &[
+    "AUDIT_WRITE",
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "FOWNER",
+    "FSETID",
+    "KILL",
+    "MKNOD",
+    "NET_BIND_SERVICE",
+    "SETFCAP",
+    "SETGID",
+    "SETPCAP",
+    "SETUID",
+    "SYS_CHROOT",
+];
+
+const SAFE_SYSCTLS: &[&str] = &[
+    "kernel.shm_rmid_forced",
+    "net.ipv4.ip_local_port_range",
+    "net.ipv4.ip_unprivileged_port_start",
+    "net.ipv4.tcp_syncookies",
+    "net.ipv4.ping_group_range",
+    "net.ipv4.ip_local_reserved_ports",
+    "net.ipv4.tcp_keepalive_time",
+    "net.ipv4.tcp_fin_timeout",
+    "net.ipv4.tcp_keepalive_intvl",
+    "net.ipv4.tcp_keepalive_probes",
+];
+
+const SELINUX_TYPES: &[&str] = &[
+    "",
+    "container_t",
+    "container_init_t",
+    "container_kvm_t",
+    "container_engine_t",
+];
+
+const RESTRICTED_VOLUME_TYPES: &[&str] = &[
+    "configMap",
+    "csi",
+    "downwardAPI",
+    "emptyDir",
+    "ephemeral",
+    "persistentVolumeClaim",
+    "projected",
+    "secret",
+];
+
+const APPARMOR_ANNOTATION: &str = "container.apparmor.security.beta.kubernetes.io/";
+
+/// One failed Pod Security Standards control, worded like the upstream
+/// PodSecurity admission plugin: `reason (detail)`.
+pub struct ControlViolation {
+    pub reason: String,
+    pub detail: String,
+    /// Fields that violate the control.
+    pub paths: Vec<String>,
+}
+
+struct Container<'a> {
+    name: &'a str,
+    path: String,
+    spec: &'a Value,
+}
+
+/// Checks the pod spec of Pods and workload controllers against every
+/// control of a profile. Objects without a pod spec have nothing to check.
+pub fn violations(level: PodSecurityLevel, kind: &str, object: &Value) -> Vec<ControlViolation> {
+    let Some((spec_path, spec)) = workload::pod_spec(kind, object) else {
+        return Vec::new();
+    };
+    let containers: Vec<Container> = workload::containers(&spec_path, spec)
+        .map(|(path, container)| Container {
+            name: container["name"].as_str().unwrap_or_default(),
+            path,
+            spec: container,
+        })
+        .collect();
+    let pod = Pod {
+        spec_path: &spec_path,
+        spec,
+        metadata: workload::pod_metadata(kind, object),
+        containers: &containers,
+    };
+
+    let mut found = Vec::new();
+    pod.host_process(&mut found);
+    pod.host_namespaces(&mut found);
+    pod.privileged(&mut found);
+    if level == PodSecurityLevel::Baseline {
+        pod.baseline_capabilities(&mut found);
+    }
+    pod.host_path_volumes(&mut found);
+    pod.host_ports(&mut found);
+    pod.app_armor(&mut found);
+    pod.se_linux(&mut found);
+    pod.proc_mount(&mut found);
+    if level == PodSecurityLevel::Baseline {
+        pod.baseline_seccomp(&mut found);
+    }
+    pod.sysctls(&mut found);
+
+    if level == PodSecurityLevel::Restricted {
+        pod.restricted_volumes(&mut found);
+        // Linux-only controls do not apply to Windows pods.
+        let windows = spec["os"]["name"] == "windows";
+        if !windows {
+            pod.allow_privilege_escalation(&mut found);
+        }
+        pod.run_as_non_root(&mut found);
+        pod.run_as_user(&mut found);
+        if !windows {
+            pod.restricted_seccomp(&mut found);
+            pod.restricted_capabilities(&mut found);
+        }
+    }
+    found
+}
+
+/// The admission message for a set of violations, as the PodSecurity plugin
+/// phrases it.
+pub fn summary(level: PodSecurityLevel, violations: &[ControlViolation]) -> String {
+    let controls: Vec<String> = violations.iter().map(ToString::to_string).collect();
+    format!(
+        "violates PodSecurity \"{}:latest\": {}",
+        level.as_str(),
+        controls.join(", ")
+    )
+}
+
+impl PodSecurityLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PodSecurityLevel::Baseline => "baseline",
+            PodSecurityLevel::Restricted => "restricted",
+        }
+    }
+}
+
+impl fmt::Display for ControlViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.reason, self.detail)
+    }
+}
+
+struct Pod<'a> {
+    spec_path: &'a str,
+    spec: &'a Value,
+    metadata: Option<&'a Value>,
+    containers: &'a [Container<'a>],
+}
+
+impl Pod<'_> {
+    fn security(&self) -> &Value {
+        &self.spec["securityContext"]
+    }
+
+    fn pod_path(&self, field: &str) -> String {
+        format!("{}.securityContext.{field}", self.spec_path)
+    }
+
+    fn host_process(&self, found: &mut Vec<ControlViolation>) {
+        let field = "windowsOptions.hostProcess";
+        let pod = self.security()["windowsOptions"]["hostProcess"] == true;
+        let containers = self
+            .containers_where(|c| c["securityContext"]["windowsOptions"]["hostProcess"] == true);
+        if pod || !containers.is_empty() {
+            found.push(ControlViolation {
+                reason: "hostProcess".to_string(),
+                detail: format!(
+                    "{} must not set securityContext.{field}=true",
+                    subjects(pod, "and", &containers)
+                ),
+                paths: self.paths(pod, field, &containers),
+            });
+        }
+    }
+
+    fn host_namespaces(&self, found: &mut Vec<ControlViolation>) {
+        let (details, paths): (Vec<_>, Vec<_>) = ["hostNetwork", "hostPID", "hostIPC"]
+            .into_iter()
+            .filter(|field| self.spec[*field] == true)
+            .map(|field| {
+                (
+                    format!("{field}=true"),
+                    format!("{}.{field}", self.spec_path),
+                )
+            })
+            .unzip();
+        if !details.is_empty() {
+            found.push(ControlViolation {
+                reason: "host namespaces".to_string(),
+                detail: details.join(", "),
+                paths,
+            });
+        }
+    }
+
+    fn privileged(&self, found: &mut Vec<ControlViolation>) {
+        let field = "privileged";
+        let containers = self.containers_where(|c| c["securityContext"]["privileged"] == true);
+        if !containers.is_empty() {
+            found.push(ControlViolation {
+                reason: "privileged".to_string(),
+                detail: format!(
+                    "{} must not set securityContext.privileged=true",
+                    subjects(false, "and", &containers)
+                ),
+                paths: self.paths(false, field, &containers),
+            });
+        }
+    }
+
+    fn baseline_capabilities(&self, found: &mut Vec<ControlViolation>) {
+        self.added_capabilities(
+            "non-default capabilities",
+            |cap| BASELINE_CAPABILITIES.contains(&cap),
+            found,
+        );
+    }
+
+    fn added_capabilities(
+        &self,
+        reason: &str,
+        allowed: impl Fn(&str) -> bool,
+        found: &mut Vec<ControlViolation>,
+    ) {
+        let field = "capabilities.add";
+        let mut forbidden = BTreeSet::new();
+        let containers = self.containers_where(|c| {
+            let added = c["securityContext"]["capabilities"]["add"].as_array();
+            let mut any = false;
+            for cap in added.into_iter().flatten().filter_map(Value::as_str) {
+                if !allowed(cap) {
+                    forbidden.insert(cap.to_string());
+                    any = true;
+                }
+            }
+            any
+        });
+        if !containers.is_empty() {
+            found.push(ControlViolation {
+                reason: reason.to_string(),
+                detail: format!(
+                    "{} must not include {} in securityContext.capabilities.add",
+                    subjects(false, "and", &containers),
+                    quoted(&forbidden)
+                ),
+                paths: self.paths(false, field, &containers),
+            });
+        }
+    }
+
+    fn host_path_volumes(&self, found: &mut Vec<ControlViolation>) {
+        let volumes: Vec<(usize, &str)> = self
+            .volumes()
+            .filter(|(_, volume)| volume.get("hostPath").is_some())
+            .map(|(index, volume)| (index, volume["name"].as_str().unwrap_or_default()))
+            .collect();
+        if !volumes.is_empty() {
+            let names: Vec<&str> = volumes.iter().map(|(_, name)| *name).collect();
+            found.push(ControlViolation {
+                reason: "hostPath volumes".to_string(),
+                detail: format!("{} {}", plural("volume", names.len()), quoted(&names)),
+                paths: volumes
+                    .iter()
+                    .map(|(index, _)| format!("{}.volumes[{index}].hostPath", self.spec_path))
+                    .collect(),
+            });
+        }
+    }
+
+    fn host_ports(&self, found: &mut Vec<ControlViolation>) {
+        let mut ports = BTreeSet::new();
+        let mut paths = Vec::new();
+        let mut names = Vec::new();
+        for container in self.containers {
+            let mut uses = false;
+            for (index, port) in container.spec["ports"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .enumerate()
+            {
+                if let Some(host_port) = port["hostPort"].as_i64().filter(|p| *p != 0) {
+                    ports.insert(host_port);
+                    paths.push(format!("{}.ports[{index}].hostPort", container.path));
+                    uses = true;
+                }
+            }
+            if uses {
+                names.push(container.name);
+            }
+        }
+        if !names.is_empty() {
+            let ports: Vec<String> = ports.iter().map(ToString::to_string).collect();
+            let verb = if names.len() == 1 { "uses" } else { "use" };
+            let noun = plural("hostPort", ports.len());
+            found.push(ControlViolation {
+                reason: "hostPort".to_string(),
+                detail: format!(
+                    "{} {verb} {noun} {}",
+                    subjects(false, "and", &names),
+                    ports.join(", ")
+                ),
+                paths,
+            });
+        }
+    }
+
+    fn app_armor(&self, found: &mut Vec<ControlViolation>) {
+        let mut details = Vec::new();
+        let mut paths = Vec::new();
+
+        let allowed_type = |t: &str| t == "RuntimeDefault" || t == "Localhost";
+        let field = "appArmorProfile.type";
+        let pod_type = self.security()["appArmorProfile"]["type"].as_str();
+        let pod = pod_type.is_some_and(|t| !allowed_type(t));
+        let mut types = BTreeSet::new();
+        if let Some(t) = pod_type.filter(|t| !allowed_type(t)) {
+            types.insert(t.to_string());
+        }
+        let containers = self.containers_where(|c| {
+            match c["securityContext"]["appArmorProfile"]["type"].as_str() {
+                Some(t) if !allowed_type(t) => {
+                    types.insert(t.to_string());
+                    true
+                }
+                _ => false,
+            }
+        });
+        if pod || !containers.is_empty() {
+            details.push(format!(
+                "{} must not set securityContext.appArmorProfile.type to {}",
+                subjects(pod, "and", &containers),
+                quoted(&types)
+            ));
+            paths.extend(self.paths(pod, field, &containers));
+        }
+
+        let annotations = self
+            .metadata
+            .and_then(|metadata| metadata["annotations"].as_object());
+        for (key, value) in annotations.into_iter().flatten() {
+            let value = value.as_str().unwrap_or_default();
+            if key.starts_with(APPARMOR_ANNOTATION)
+                && value != "runtime/default"
+                && !value.starts_with("localhost/")
+            {
+                details.push(format!("{key}={value:?}"));
+                let metadata_path = self
+                    .spec_path
+                    .strip_suffix("spec")
+                    .map(|parent| format!("{parent}metadata"))
+                    .unwrap_or_else(|| "metadata".to_string());
+                paths.push(format!("{metadata_path}.annotations['{key}']"));
+            }
+        }
+
+        if !details.is_empty() {
+            found.push(ControlViolation {
+                reason: plural("forbidden AppArmor profile", details.len()),
+                detail: details.join(", "),
+                paths,
+            });
+        }
+    }
+
+    fn se_linux(&self, found: &mut Vec<ControlViolation>) {
+        let field = "seLinuxOptions";
+        let mut types = BTreeSet::new();
+        let mut users = BTreeSet::new();
+        let mut roles = BTreeSet::new();
+        let mut forbidden = |options: &Value| {
+            let mut bad = false;
+            if let Some(t) = options["type"].as_str()
+                && !SELINUX_TYPES.contains(&t)
+            {
+                types.insert(t.to_string());
+                bad = true;
+            }
+            if let Some(user) = options["user"].as_str().filter(|u| !u.is_empty()) {
+                users.insert(user.to_string());
+                bad = true;
+            }
+            if let Some(role) = options["role"].as_str().filter(|r| !r.is_empty()) {
+                roles.insert(role.to_string());
+                bad = true;
+            }
+            bad
+        };
+        let pod = forbidden(&self.security()["seLinuxOptions"]);
+        let containers =
+            self.containers_where(|c| forbidden(&c["securityContext"]["seLinuxOptions"]));
+        if pod || !containers.is_empty() {
+            let mut parts = Vec::new();
+            if !types.is_empty() {
+                parts.push(format!(
+                    "{} {}",
+                    plural("type", types.len()),
+                    quoted(&types)
+                ));
+            }
+            if !users.is_empty() {
+                parts.push(format!(
+                    "{} {}",
+                    plural("user", users.len()),
+                    quoted(&users)
+                ));
+            }
+            if !roles.is_empty() {
+                parts.push(format!(
+                    "{} {}",
+                    plural("role", roles.len()),
+                    quoted(&roles)
+                ));
+            }
+            found.push(ControlViolation {
+                reason: "seLinuxOptions".to_string(),
+                detail: format!(
+                    "{} set forbidden securityContext.seLinuxOptions: {}",
+                    subjects(pod, "and", &containers),
+                    parts.join("; ")
+                ),
+                paths: self.paths(pod, field, &containers),
+            });
+        }
+    }
+
+    fn proc_mount(&self, found: &mut Vec<ControlViolation>) {
+        let field = "procMount";
+        let mut values = BTreeSet::new();
+        let containers =
+            self.containers_where(|c| match c["securityContext"]["procMount"].as_str() {
+                Some(mount) if mount != "Default" => {
+                    values.insert(mount.to_string());
+                    true
+                }
+                _ => false,
+            });
+        if !containers.is_empty() {
+            found.push(ControlViolation {
+                reason: "procMount".to_string(),
+                detail: format!(
+                    "{} must not set securityContext.procMount to {}",
+                    subjects(false, "and", &containers),
+                    quoted(&values)
+                ),
+                paths: self.paths(false, field, &containers),
+            });
+        }
+    }
+
+    fn baseline_seccomp(&self, found: &mut Vec<ControlViolation>) {
+        let field = "seccompProfile.type";
+        let unconfined = |sc: &Value| sc["seccompProfile"]["type"] == "Unconfined";
+        let pod = unconfined(self.security());
+        let containers = self.containers_where(|c| unconfined(&c["securityContext"]));
+        if pod || !containers.is_empty() {
+            found.push(ControlViolation {
+                reason: "seccompProfile".to_string(),
+                detail: format!(
+                    "{} must not set securityContext.seccompProfile.type to \"Unconfined\"",
+                    subjects(pod, "and", &containers)
+                ),
+                paths: self.paths(pod, field, &containers),
+            });
+        }
+    }
+
+    fn sysctls(&self, found: &mut Vec<ControlViolation>) {
+        let (names, paths): (Vec<_>, Vec<_>) = self.security()["sysctls"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(index, sysctl)| {
+                let name = sysctl["name"].as_str()?;
+                (!SAFE_SYSCTLS.contains(&name))
+                    .then(|| (name, self.pod_path(&format!("sysctls[{index}].name"))))
+            })
+            .unzip();
+        if !names.is_empty() {
+            found.push(ControlViolation {
+                reason: "forbidden sysctls".to_string(),
+                detail: names.join(", "),
+                paths,
+            });
+        }
+    }
+
+    fn restricted_volumes(&self, found: &mut Vec<ControlViolation>) {
+        let mut names = Vec::new();
+        let mut types = BTreeSet::new();
+        let mut paths = Vec::new();
+        for (index, volume) in self.volumes() {
+            let restricted = volume
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(field, _)| field.as_str())
+                .find(|field| *field != "name" && !RESTRICTED_VOLUME_TYPES.contains(field));
+            if let Some(volume_type) = restricted {
+                names.push(volume["name"].as_str().unwrap_or_default());
+                types.insert(volume_type.to_string());
+                paths.push(format!("{}.volumes[{index}].{volume_type}", self.spec_path));
+            }
+        }
+        if !names.is_empty() {
+            let verb = if names.len() == 1 { "uses" } else { "use" };
+            found.push(ControlViolation {
+                reason: "restricted volume types".to_string(),
+                detail: format!(
+                    "{} {} {verb} {} {}",
+                    plural("volume", names.len()),
+                    quoted(&names),
+                    plural("restricted volume type", types.len()),
+                    quoted(&types)
+                ),
+                paths,
+            });
+        }
+    }
+
+    fn allow_privilege_escalation(&self, found: &mut Vec<ControlViolation>) {
+        let field = "allowPrivilegeEscalation";
+        let containers =
+            self.containers_where(|c| c["securityContext"]["allowPrivilegeEscalation"] != false);
+        if !containers.is_empty() {
+            found.push(ControlViolation {
+                reason: "allowPrivilegeEscalation != false".to_string(),
+                detail: format!(
+                    "{} must set securityContext.allowPrivilegeEscalation=false",
+                    subjects(false, "and", &containers)
+                ),
+                paths: self.paths(false, field, &containers),
+            });
+        }
+    }
+
+    fn run_as_non_root(&self, found: &mut Vec<ControlViolation>) {
+        let field = "runAsNonRoot";
+        let pod_value = self.security()["runAsNonRoot"].as_bool();
+        let explicit_pod = pod_value == Some(false);
+        let explicit = self.containers_where(|c| c["securityContext"]["runAsNonRoot"] == false);
+        // Containers that inherit an unset pod-level value.
+        let implicit = if pod_value == Some(true) {
+            Vec::new()
+        } else {
+            self.containers_where(|c| c["securityContext"]["runAsNonRoot"].is_null())
+        };
+
+        let mut details = Vec::new();
+        let mut paths = Vec::new();
+        if explicit_pod || !explicit.is_empty() {
+            details.push(format!(
+                "{} must not set securityContext.runAsNonRoot=false",
+                subjects(explicit_pod, "and", &explicit)
+            ));
+            paths.extend(self.paths(explicit_pod, field, &explicit));
+        }
+        if !implicit.is_empty() {
+            details.push(format!(
+                "{} must set securityContext.runAsNonRoot=true",
+                subjects(true, "or", &implicit)
+            ));
+            paths.extend(self.paths(pod_value.is_none(), field, &implicit));
+        }
+        if !details.is_empty() {
+            found.push(ControlViolation {
+                reason: "runAsNonRoot != true".to_string(),
+                detail: details.join("; "),
+                paths,
+            });
+        }
+    }
+
+    fn run_as_user(&self, found: &mut Vec<ControlViolation>) {
+        let field = "runAsUser";
+        let pod = self.security()["runAsUser"] == 0;
+        let containers = self.containers_where(|c| c["securityContext"]["runAsUser"] == 0);
+        if pod || !containers.is_empty() {
+            found.push(ControlViolation {
+                reason: "runAsUser=0".to_string(),
+                detail: format!(
+                    "{} must not set runAsUser=0",
+                    subjects(pod, "and", &containers)
+                ),
+                paths: self.paths(pod, field, &containers),
+            });
+        }
+    }
+
+    fn restricted_seccomp(&self, found: &mut Vec<ControlViolation>) {
+        let field = "seccompProfile.type";
+        let valid = |t: &str| t == "RuntimeDefault" || t == "Localhost";
+        let pod_type = self.security()["seccompProfile"]["type"].as_str();
+
+        let mut types = BTreeSet::new();
+        let explicit_pod = pod_type.is_some_and(|t| !valid(t));
+        if let Some(t) = pod_type.filter(|t| !valid(t)) {
+            types.insert(t.to_string());
+        }
+        let explicit = self.containers_where(|c| {
+            match c["securityContext"]["seccompProfile"]["type"].as_str() {
+                Some(t) if !valid(t) => {
+                    types.insert(t.to_string());
+                    true
+                }
+                _ => false,
+            }
+        });
+        let implicit = if pod_type.is_some() {
+            Vec::new()
+        } else {
+            self.containers_where(|c| c["securityContext"]["seccompProfile"]["type"].is_null())
+        };
+
+        let mut details = Vec::new();
+        let mut paths = Vec::new();
+        if explicit_pod || !explicit.is_empty() {
+            details.push(format!(
+                "{} must not set securityContext.seccompProfile.type to {}",
+                subjects(explicit_pod, "and", &explicit),
+                quoted(&types)
+            ));
+            paths.extend(self.paths(explicit_pod, field, &explicit));
+        }
+        if !implicit.is_empty() {
+            details.push(format!(
+                "{} must set securityContext.seccompProfile.type to \"RuntimeDefault\" or \"Localhost\"",
+                subjects(true, "or", &implicit)
+            ));
+            paths.extend(self.paths(true, field, &implicit));
+        }
+        if !details.is_empty() {
+            found.push(ControlViolation {
+                reason: "seccompProfile".to_string(),
+                detail: details.join("; "),
+                paths,
+            });
+        }
+    }
+
+    fn restricted_capabilities(&self, found: &mut Vec<ControlViolation>) {
+        let field = "capabilities.drop";
+        let containers = self.containers_where(|c| {
+            !c["securityContext"]["capabilities"]["drop"]
+                .as_array()
+                .is_some_and(|drop| drop.iter().any(|cap| cap == "ALL"))
+        });
+        let mut added = Vec::new();
+        self.added_capabilities(
+            "unrestricted capabilities",
+            |cap| cap == "NET_BIND_SERVICE",
+            &mut added,
+        );
+
+        let mut details = Vec::new();
+        let mut paths = Vec::new();
+        if !containers.is_empty() {
+            details.push(format!(
+                "{} must set securityContext.capabilities.drop=[\"ALL\"]",
+                subjects(false, "and", &containers)
+            ));
+            paths.extend(self.paths(false, field, &containers));
+        }
+        for violation in added {
+            details.push(violation.detail);
+            paths.extend(violation.paths);
+        }
+        if !details.is_empty() {
+            found.push(ControlViolation {
+                reason: "unrestricted capabilities".to_string(),
+                detail: details.join("; "),
+                paths,
+            });
+        }
+    }
+
+    fn volumes(&self) -> impl Iterator<Item = (usize, &Value)> {
+        self.spec["volumes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+    }
+
+    /// Names of the containers for which `violates` holds.
+    fn containers_where(&self, mut violates: impl FnMut(&Value) -> bool) -> Vec<&str> {
+        self.containers
+            .iter()
+            .filter(|container| violates(container.spec))
+            .map(|container| container.name)
+            .collect()
+    }
+
+    fn paths(&self, pod: bool, field: &str, containers: &[&str]) -> Vec<String> {
+        let mut paths = Vec::new();
+        if pod {
+            paths.push(self.pod_path(field));
+        }
+        for container in self.containers {
+            if containers.contains(&container.name) {
+                paths.push(format!("{}.securityContext.{field}", container.path));
+            }
+        }
+        paths
+    }
+}
+
+/// `pod`, `container "a"`, `containers "a", "b"` or `pod and container "a"`.
+fn subjects(pod: bool, conjunction: &str, containers: &[&str]) -> String {
+    let containers = (!containers.is_empty()).then(|| {
+        format!(
+            "{} {}",
+            plural("container", containers.len()),
+            quoted(containers)
+        )
+    });
+    match (pod, containers) {
+        (true, Some(containers)) => format!("pod {conjunction} {containers}"),
+        (true, None) => "pod".to_string(),
+        (false, Some(containers)) => containers,
+        (false, None) => String::new(),
+    }
+}
+
+fn plural(noun: &str, count: usize) -> String {
+    if count == 1 {
+        noun.to_string()
+    } else {
+        format!("{noun}s")
+    }
+}
+
+fn quoted<T: AsRef<str>>(items: impl IntoIterator<Item = T>) -> String {
+    items
+        .into_iter()
+        .map(|item| format!("{:?}", item.as_ref()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use PodSecurityLevel::{Baseline, Restricted};
+
+    /// A pod spec that satisfies the restricted profile.
+    fn restricted_spec() -> Value {
+        json!({
+            "securityContext": {
+                "runAsNonRoot": true,
+                "seccompProfile": { "type": "RuntimeDefault" },
+            },
+            "containers": [{
+                "name": "app",
+                "image": "nginx:1.27",
+                "securityContext": {
+                    "allowPrivilegeEscalation": false,
+                    "capabilities": { "drop": ["ALL"] },
+                },
+            }],
+        })
+    }
+
+    /// Sets the field at `pointer` below the pod spec, creating objects on the way.
+    fn set(mut spec: Value, pointer: &str, value: Value) -> Value {
+        let mut target = &mut spec;
+        for segment in pointer.split('/').skip(1) {
+            target = match target {
+                Value::Array(items) => &mut items[segment.parse::<usize>().unwrap()],
+                _ => &mut target[segment],
+            };
+        }
+        *target = value;
+        spec
+    }
+
+    fn check(level: PodSecurityLevel, spec: &Value) -> Vec<ControlViolation> {
+        violations(level, "Pod", &json!({ "spec": spec }))
+    }
+
+    fn reasons(level: PodSecurityLevel, spec: &Value) -> Vec<String> {
+        check(level, spec)
+            .into_iter()
+            .map(|violation| violation.reason)
+            .collect()
+    }
+
+    /// The one violation of `reason`, as `reason (detail)`.
+    fn violation(level: PodSecurityLevel, spec: &Value, reason: &str) -> String {
+        let found = check(level, spec);
+        let matching: Vec<_> = found.iter().filter(|v| v.reason == reason).collect();
+        assert_eq!(matching.len(), 1, "{reason} in {:?}", reasons(level, spec));
+        matching[0].to_string()
+    }
+
+    #[test]
+    fn restricted_pods_pass_both_levels() {
+        assert!(check(Baseline, &restricted_spec()).is_empty());
+        assert!(check(Restricted, &restricted_spec()).is_empty());
+    }
+
+    #[test]
+    fn bare_pods_pass_baseline_but_not_restricted() {
+        let spec = json!({ "containers": [{ "name": "app", "image": "nginx:1.27" }] });
+        assert!(check(Baseline, &spec).is_empty());
+        assert_eq!(
+            reasons(Restricted, &spec),
+            [
+                "allowPrivilegeEscalation != false",
+                "runAsNonRoot != true",
+                "seccompProfile",
+                "unrestricted capabilities",
+            ]
+        );
+    }
+
+    #[test]
+    fn baseline_controls_apply_at_both_levels() {
+        let cases = [
+            (
+                set(restricted_spec(), "/hostNetwork", json!(true)),
+                "host namespaces (hostNetwork=true)",
+            ),
+            (
+                set(
+                    restricted_spec(),
+                    "/containers/0/securityContext/privileged",
+                    json!(true),
+                ),
+                "privileged (container \"app\" must not set securityContext.privileged=true)",
+            ),
+            (
+                set(
+                    restricted_spec(),
+                    "/volumes",
+                    json!([{ "name": "root", "hostPath": { "path": "/" } }]),
+                ),
+                "hostPath volumes (volume \"root\")",
+            ),
+            (
+                set(
+                    restricted_spec(),
+                    "/containers/0/ports",
+                    json!([{ "containerPort": 80, "hostPort": 8080 }]),
+                ),
+                "hostPort (container \"app\" uses hostPort 8080)",
+            ),
+            (
+                set(
+                    restricted_spec(),
+                    "/securityContext/windowsOptions/hostProcess",
+                    json!(true),
+                ),
+                "hostProcess (pod must not set securityContext.windowsOptions.hostProcess=true)",
+            ),
+            (
+                set(
+                    restricted_spec(),
+                    "/containers/0/securityContext/appArmorProfile/type",
+                    json!("Unconfined"),
+                ),
+                "forbidden AppArmor profile (container \"app\" must not set securityContext.appArmorProfile.type to \"Unconfined\")",
+            ),
+            (
+                set(
+                    restricted_spec(),
+                    "/securityContext/seLinuxOptions/type",
+                    json!("spc_t"),
+                ),
+                "seLinuxOptions (pod set forbidden securityContext.seLinuxOptions: type \"spc_t\")",
+            ),
+            (
+                set(
+                    restricted_spec(),
+                    "/containers/0/securityContext/procMount",
+                    json!("Unmasked"),
+                ),
+                "procMount (container \"app\" must not set securityContext.procMount to \"Unmasked\")",
+            ),
+            (
+                set(
+                    restricted_spec(),
+                    "/securityContext/sysctls",
+                    json!([{ "name": "kernel.msgmax", "value": "65536" }]),
+                ),
+                "forbidden sysctls (kernel.msgmax)",
+            ),
+        ];
+        for (spec, expected) in cases {
+            let reason = expected.split(" (").next().unwrap();
+            assert_eq!(violation(Baseline, &spec, reason), expected);
+            assert_eq!(violation(Restricted, &spec, reason), expected);
+        }
+    }
+
+    #[test]
+    fn safe_values_pass_baseline() {
+        let spec = restricted_spec();
+        let spec = set(
+            spec,
+            "/containers/0/ports",
+            json!([{ "containerPort": 80 }]),
+        );
+        let spec = set(
+            spec,
+            "/securityContext/sysctls",
+            json!([{ "name": "net.ipv4.tcp_syncookies", "value": "1" }]),
+        );
+        let spec = set(
+            spec,
+            "/securityContext/seLinuxOptions",
+            json!({ "type": "container_t", "level": "s0:c123,c456" }),
+        );
+        let spec = set(
+            spec,
+            "/containers/0/securityContext/procMount",
+            json!("Default"),
+        );
+        assert!(check(Baseline, &spec).is_empty());
+        assert!(check(Restricted, &spec).is_empty());
+    }
+
+    #[test]
+    fn app_armor_annotations_are_checked() {
+        let pod = json!({
+            "metadata": { "annotations": {
+                "container.apparmor.security.beta.kubernetes.io/app": "unconfined",
+            }},
+            "spec": restricted_spec(),
+        });
+        let found = violations(Baseline, "Pod", &pod);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].to_string(),
+            "forbidden AppArmor profile (container.apparmor.security.beta.kubernetes.io/app=\"unconfined\")"
+        );
+        assert_eq!(
+            found[0].paths,
+            ["metadata.annotations['container.apparmor.security.beta.kubernetes.io/app']"]
+        );
+    }
+
+    #[test]
+    fn capabilities_differ_by_level() {
+        let spec = set(
+            restricted_spec(),
+            "/containers/0/securityContext/capabilities/add",
+            json!(["CHOWN"]),
+        );
+        assert!(check(Baseline, &spec).is_empty());
+        assert_eq!(
+            violation(Restricted, &spec, "unrestricted capabilities"),
+            "unrestricted capabilities (container \"app\" must not include \"CHOWN\" in securityContext.capabilities.add)"
+        );
+
+        let spec = set(
+            restricted_spec(),
+            "/containers/0/securityContext/capabilities/add",
+            json!(["SYS_ADMIN"]),
+        );
+        assert_eq!(
+            violation(Baseline, &spec, "non-default capabilities"),
+            "non-default capabilities (container \"app\" must not include \"SYS_ADMIN\" in securityContext.capabilities.add)"
+        );
+
+        let spec = set(
+            restricted_spec(),
+            "/containers/0/securityContext/capabilities/add",
+            json!(["NET_BIND_SERVICE"]),
+        );
+        assert!(check(Restricted, &spec).is_empty());
+    }
+
+    #[test]
+    fn seccomp_differs_by_level() {
+        let spec = set(
+            restricted_spec(),
+            "/securityContext/seccompProfile/type",
+            json!("Unconfined"),
+        );
+        let baseline = "seccompProfile (pod must not set securityContext.seccompProfile.type to \"Unconfined\")";
+        assert_eq!(violation(Baseline, &spec, "seccompProfile"), baseline);
+        assert_eq!(violation(Restricted, &spec, "seccompProfile"), baseline);
+
+        // Baseline allows leaving the profile unset.
+        let spec = set(
+            restricted_spec(),
+            "/securityContext/seccompProfile",
+            json!(null),
+        );
+        assert!(check(Baseline, &spec).is_empty());
+        assert_eq!(
+            violation(Restricted, &spec, "seccompProfile"),
+            "seccompProfile (pod or container \"app\" must set securityContext.seccompProfile.type to \"RuntimeDefault\" or \"Localhost\")"
+        );
+    }
+
+    #[test]
+    fn restricted_volume_types() {
+        let spec = set(
+            restricted_spec(),
+            "/volumes",
+            json!([
+                { "name": "config", "configMap": { "name": "app" } },
+                { "name": "data", "nfs": { "server": "nfs", "path": "/" } },
+            ]),
+        );
+        assert!(check(Baseline, &spec).is_empty());
+        let found = check(Restricted, &spec);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].to_string(),
+            "restricted volume types (volume \"data\" uses restricted volume type \"nfs\")"
+        );
+        assert_eq!(found[0].paths, ["spec.volumes[1].nfs"]);
+    }
+
+    #[test]
+    fn run_as_user_zero_is_restricted() {
+        let spec = set(
+            restricted_spec(),
+            "/containers/0/securityContext/runAsUser",
+            json!(0),
+        );
+        assert!(check(Baseline, &spec).is_empty());
+        assert_eq!(
+            violation(Restricted, &spec, "runAsUser=0"),
+            "runAsUser=0 (container \"app\" must not set runAsUser=0)"
+        );
+    }
+
+    #[test]
+    fn containers_inherit_run_as_non_root_from_the_pod() {
+        // Set on the pod only.
+        assert!(check(Restricted, &restricted_spec()).is_empty());
+
+        // Set on the container only.
+        let spec = set(
+            restricted_spec(),
+            "/securityContext/runAsNonRoot",
+            json!(null),
+        );
+        let spec = set(
+            spec,
+            "/containers/0/securityContext/runAsNonRoot",
+            json!(true),
+        );
+        assert!(check(Restricted, &spec).is_empty());
+
+        // Set on neither.
+        let spec = set(
+            restricted_spec(),
+            "/securityContext/runAsNonRoot",
+            json!(null),
+        );
+        let found = check(Restricted, &spec);
+        assert_eq!(
+            found[0].to_string(),
+            "runAsNonRoot != true (pod or container \"app\" must set securityContext.runAsNonRoot=true)"
+        );
+        assert_eq!(
+            found[0].paths,
+            [
+                "spec.securityContext.runAsNonRoot",
+                "spec.containers[0].securityContext.runAsNonRoot",
+            ]
+        );
+
+        // A container may not override the pod with false.
+        let spec = set(
+            restricted_spec(),
+            "/containers/0/securityContext/runAsNonRoot",
+            json!(false),
+        );
+        assert_eq!(
+            violation(Restricted, &spec, "runAsNonRoot != true"),
+            "runAsNonRoot != true (container \"app\" must not set securityContext.runAsNonRoot=false)"
+        );
+    }
+
+    #[test]
+    fn containers_inherit_seccomp_from_the_pod() {
+        // Set on the container only.
+        let spec = set(
+            restricted_spec(),
+            "/securityContext/seccompProfile",
+            json!(null),
+        );
+        let spec = set(
+            spec,
+            "/containers/0/securityContext/seccompProfile/type",
+            json!("Localhost"),
+        );
+        assert!(check(Restricted, &spec).is_empty());
+
+        // A container may not override the pod with Unconfined.
+        let spec = set(
+            restricted_spec(),
+            "/containers/0/securityContext/seccompProfile/type",
+            json!("Unconfined"),
+        );
+        let found = check(Restricted, &spec);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].to_string(),
+            "seccompProfile (container \"app\" must not set securityContext.seccompProfile.type to \"Unconfined\")"
+        );
+        assert_eq!(
+            found[0].paths,
+            ["spec.containers[0].securityContext.seccompProfile.type"]
+        );
+    }
+
+    #[test]
+    fn windows_pods_skip_linux_only_controls() {
+        let spec = json!({
+            "os": { "name": "windows" },
+            "containers": [{ "name": "app", "image": "mcr.microsoft.com/windows/nanoserver:ltsc2022" }],
+        });
+        assert_eq!(reasons(Restricted, &spec), ["runAsNonRoot != true"]);
+
+        let spec = set(spec, "/securityContext/runAsNonRoot", json!(true));
+        assert!(check(Restricted, &spec).is_empty());
+
+        // Baseline controls still apply.
+        let spec = set(spec, "/hostNetwork", json!(true));
+        assert_eq!(reasons(Restricted, &spec), ["host namespaces"]);
+    }
+
+    #[test]
+    fn workload_templates_and_init_containers_are_checked() {
+        let template = set(
+            restricted_spec(),
+            "/initContainers",
+            json!([{ "name": "init", "securityContext": { "privileged": true } }]),
+        );
+        let deployment = json!({ "spec": { "template": { "spec": template } } });
+        let found = violations(Baseline, "Deployment", &deployment);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].paths,
+            ["spec.template.spec.initContainers[0].securityContext.privileged"]
+        );
+        assert!(violations(Restricted, "ConfigMap", &json!({ "data": {} })).is_empty());
+    }
+
+    #[test]
+    fn summary_joins_violations_like_pod_security_admission() {
+        let spec = json!({ "containers": [
+            { "name": "app", "image": "nginx:1.27" },
+            { "name": "sidecar", "image": "envoy:1.31" },
+        ]});
+        let spec = set(spec, "/hostPID", json!(true));
+        let spec = set(spec, "/securityContext/runAsNonRoot", json!(true));
+        let spec = set(
+            spec,
+            "/securityContext/seccompProfile/type",
+            json!("RuntimeDefault"),
+        );
+
+        assert_eq!(
+            summary(Baseline, &check(Baseline, &spec)),
+            "violates PodSecurity \"baseline:latest\": host namespaces (hostPID=true)"
+        );
+        assert_eq!(
+            summary(Restricted, &check(Restricted, &spec)),
+            "violates PodSecurity \"restricted:latest\": host namespaces (hostPID=true), \
+             allowPrivilegeEscalation != false (containers \"app\", \"sidecar\" must set \
+             securityContext.allowPrivilegeEscalation=false), unrestricted capabilities \
+             (containers \"app\", \"sidecar\" must set securityContext.capabilities.drop=[\"ALL\"])"
+        );
+    }
+}
@@ -1,4 +1,559 @@
+use std::collections::{BTreeMap, HashSet};
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use kube::ResourceExt;
+use regex::Regex;
+use serde_json::Value;
+
+use super::jsonpath::JsonPath;
+use super::mutation;
+use crate::crd::{
+    EnforcementAction, FailurePolicy, FieldPattern, GuardianPolicy, MatchResources, PolicyRule,
+    Severity,
+};
+
 pub struct Policy {
     pub name: String,
     pub enabled: bool,
+    pub severity: Severity,
+    pub enforcement_action: EnforcementAction,
+    pub failure_policy: FailurePolicy,
+    pub rules: Vec<PolicyRule>,
+}
+
+/// The identifying parts of an object that rule match blocks select on.
+pub struct Target {
+    pub api_group: String,
+    pub kind: String,
+    pub namespace: Option<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl From<&GuardianPolicy> for Policy {
+    fn from(policy: &GuardianPolicy) -> Self {
+        Policy {
+            name: policy.name_any(),
+            enabled: policy.metadata.deletion_timestamp.is_none(),
+            severity: policy.spec.severity,
+            enforcement_action: policy.spec.enforcement_action,
+            failure_policy: policy.spec.failure_policy,
+            rules: policy.spec.rules.clone(),
+        }
+    }
+}
+
+impl Target {
+    /// Reads the target fields from a Kubernetes object in its JSON form.
+    pub fn from_object(object: &Value) -> Target {
+        let api_version = object["apiVersion"].as_str().unwrap_or_default();
+        let api_group = match api_version.split_once('/') {
+            Some((group, _)) => group.to_string(),
+            None => String::new(),
+        };
+        let labels = object["metadata"]["labels"]
+            .as_object()
+            .map(|labels| {
+                labels
+                    .iter()
+                    .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Target {
+            api_group,
+            kind: object["kind"].as_str().unwrap_or_default().to_string(),
+            namespace: object["metadata"]["namespace"].as_str().map(str::to_string),
+            labels,
+        }
+    }
+}
+
+impl Policy {
+    pub fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+        let mut seen = HashSet::new();
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            if rule.name.trim().is_empty() {
+                problems.push(format!("rules[{index}]: name must not be empty"));
+            } else if !seen.insert(rule.name.as_str()) {
+                problems.push(format!(
+                    "rules[{index}]: duplicate rule name {:?}",
+                    rule.name
+                ));
+            }
+            match &rule.condition {
+                Some(condition) if condition.trim().is_empty() => {
+                    problems.push(format!("rules[{index}]: condition must not be empty"));
+                }
+                Some(_) if rule.message.trim().is_empty() => {
+                    problems.push(format!("rules[{index}]: message must not be empty"));
+                }
+                Some(_) => {}
+                None if rule.defaults.is_none()
+                    && rule.patterns.is_empty()
+                    && rule.builtin.is_none()
+                    && rule.pod_security.is_none() =>
+                {
+                    problems.push(format!(
+                        "rules[{index}]: a rule needs a condition, patterns, a builtin, podSecurity or defaults"
+                    ));
+                }
+                None => {}
+            }
+            for (p, pattern) in rule.patterns.iter().enumerate() {
+                let checks = [
+                    pattern.exists,
+                    pattern.absent,
+                    pattern.equals.is_some(),
+                    pattern.matches.is_some(),
+                    !pattern.one_of.is_empty(),
+                ];
+                if checks.iter().filter(|set| **set).count() != 1 {
+                    problems.push(format!(
+                        "rules[{index}].patterns[{p}]: set exactly one of exists, absent, equals, matches or oneOf"
+                    ));
+                }
+            }
+            if let Some(selector) = &rule.match_resources.label_selector
+                && let Err(e) = validate_selector(selector)
+            {
+                problems.push(format!("rules[{index}].match.labelSelector: {e}"));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("; "))
+        }
+    }
+
+    pub fn rules_for<'a>(&'a self, target: &'a Target) -> impl Iterator<Item = &'a PolicyRule> {
+        self.rules
+            .iter()
+            .filter(move |rule| rule.match_resources.matches(target))
+    }
+
+    /// Applies the defaults of every rule that selects the target and returns
+    /// the names of the rules that changed `object`.
+    pub fn apply_defaults(&self, target: &Target, object: &mut Value) -> Vec<String> {
+        let mut applied = Vec::new();
+        for rule in self.rules_for(target) {
+            if let Some(defaults) = &rule.defaults {
+                let before = object.clone();
+                mutation::apply_defaults(defaults, &target.kind, object);
+                if *object != before {
+                    applied.push(rule.name.clone());
+                }
+            }
+        }
+        applied
+    }
+}
+
+/// A [`FieldPattern`] with its path parsed and regular expression compiled.
+pub struct Pattern {
+    path: JsonPath,
+    check: Check,
+}
+
+enum Check {
+    Exists,
+    Absent,
+    Equals(String),
+    Matches(Regex),
+    OneOf(Vec<String>),
+}
+
+/// A field that failed a pattern or built-in check.
+pub struct FieldFailure {
+    pub path: String,
+    pub message: String,
+}
+
+impl Pattern {
+    pub fn compile(pattern: &FieldPattern) -> Result<Pattern, String> {
+        let path = JsonPath::parse(&pattern.path)?;
+        let check = if pattern.exists {
+            Check::Exists
+        } else if pattern.absent {
+            Check::Absent
+        } else if let Some(value) = &pattern.equals {
+            Check::Equals(value.clone())
+        } else if let Some(regex) = &pattern.matches {
+            let regex = Regex::new(regex)
+                .map_err(|e| format!("{}: invalid regular expression: {e}", pattern.path))?;
+            Check::Matches(regex)
+        } else if !pattern.one_of.is_empty() {
+            Check::OneOf(pattern.one_of.clone())
+        } else {
+            return Err(format!("{}: pattern has no check", pattern.path));
+        };
+        Ok(Pattern { path, check })
+    }
+
+    /// Every field the path selects in `object` that fails the check.
+    pub fn failures(&self, object: &Value) -> Vec<FieldFailure> {
+        self.path
+            .select(object)
+            .into_iter()
+            .filter_map(|(path, value)| {
+                let message = self.failure(value)?;
+                Some(FieldFailure {
+                    message: format!("{path} {message}"),
+                    path,
+                })
+            })
+            .collect()
+    }
+
+    fn failure(&self, value: Option<&Value>) -> Option<String> {
+        let text = value.map(as_text);
+        let text = text.as_deref();
+        match &self.check {
+            Check::Exists if text.is_none() => Some("must be set".to_string()),
+            Check::Absent if text.is_some() => Some("must not be set".to_string()),
+            Check::Equals(expected) if text != Some(expected) => {
+                Some(format!("must equal {expected:?}"))
+            }
+            Check::Matches(regex) if !text.is_some_and(|t| regex.is_match(t)) => {
+                Some(format!("must match {:?}", regex.as_str()))
+            }
+            Check::OneOf(allowed) if !text.is_some_and(|t| allowed.iter().any(|a| a == t)) => {
+                Some(format!("must be one of {}", allowed.join(", ")))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Scalars compare by their plain text so `equals: "true"` matches a JSON bool.
+fn as_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl MatchResources {
+    pub fn matches(&self, target: &Target) -> bool {
+        let any = |list: &[String], value: &str| {
+            list.is_empty() || list.iter().any(|v| v == "*" || v == value)
+        };
+
+        if !any(&self.kinds, &target.kind) || !any(&self.api_groups, &target.api_group) {
+            return false;
+        }
+        if !self.namespaces.is_empty() {
+            match &target.namespace {
+                Some(ns) if any(&self.namespaces, ns) => {}
+                _ => return false,
+            }
+        }
+        match &self.label_selector {
+            Some(selector) => selector_matches(selector, &target.labels),
+            None => true,
+        }
+    }
+}
+
+fn validate_selector(selector: &LabelSelector) -> Result<(), String> {
+    for expr in selector.match_expressions.iter().flatten() {
+        let has_values = expr.values.as_ref().is_some_and(|v| !v.is_empty());
+        match expr.operator.as_str() {
+            "In" | "NotIn" if !has_values => {
+                return Err(format!(
+                    "operator {} on {:?} requires values",
+                    expr.operator, expr.key
+                ));
+            }
+            "Exists" | "DoesNotExist" if has_values => {
+                return Err(format!(
+                    "operator {} on {:?} must not have values",
+                    expr.operator, expr.key
+                ));
+            }
+            "In" | "NotIn" | "Exists" | "DoesNotExist" => {}
+            other => return Err(format!("unknown operator {other:?}")),
+        }
+    }
+    Ok(())
+}
+
+fn selector_matches(selector: &LabelSelector, labels: &BTreeMap<String, String>) -> bool {
+    let labels_match = selector
+        .match_labels
+        .iter()
+        .flatten()
+        .all(|(k, v)| labels.get(k) == Some(v));
+
+    labels_match
+        && selector.match_expressions.iter().flatten().all(|expr| {
+            let value = labels.get(&expr.key);
+            let listed = |v: &String| expr.values.iter().flatten().any(|candidate| candidate == v);
+            match expr.operator.as_str() {
+                "In" => value.is_some_and(listed),
+                "NotIn" => !value.is_some_and(listed),
+                "Exists" => value.is_some(),
+                "DoesNotExist" => value.is_none(),
+                _ => false,
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn selector(value: Value) -> LabelSelector {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn target(kind: &str, namespace: Option<&str>) -> Target {
+        Target {
+            api_group: "apps".to_string(),
+            kind: kind.to_string(),
+            namespace: namespace.map(str::to_string),
+            labels: labels(&[("team", "a")]),
+        }
+    }
+
+    fn expression(operator: &str, values: &[&str]) -> LabelSelector {
+        selector(json!({
+            "matchExpressions": [{ "key": "tier", "operator": operator, "values": values }]
+        }))
+    }
+
+    #[test]
+    fn match_labels_require_every_pair() {
+        let selector = selector(json!({ "matchLabels": { "team": "a", "tier": "web" } }));
+        assert!(selector_matches(
+            &selector,
+            &labels(&[("team", "a"), ("tier", "web"), ("extra", "x")])
+        ));
+        assert!(!selector_matches(&selector, &labels(&[("team", "a")])));
+        assert!(!selector_matches(
+            &selector,
+            &labels(&[("team", "a"), ("tier", "db")])
+        ));
+    }
+
+    #[test]
+    fn in_and_not_in_compare_against_values() {
+        let web = labels(&[("tier", "web")]);
+        let db = labels(&[("tier", "db")]);
+        let unlabeled = labels(&[]);
+
+        let selector = expression("In", &["web", "api"]);
+        assert!(selector_matches(&selector, &web));
+        assert!(!selector_matches(&selector, &db));
+        assert!(!selector_matches(&selector, &unlabeled));
+
+        let selector = expression("NotIn", &["web", "api"]);
+        assert!(!selector_matches(&selector, &web));
+        assert!(selector_matches(&selector, &db));
+        assert!(selector_matches(&selector, &unlabeled));
+    }
+
+    #[test]
+    fn exists_and_does_not_exist_check_the_key_only() {
+        let web = labels(&[("tier", "web")]);
+        let unlabeled = labels(&[]);
+
+        let selector = expression("Exists", &[]);
+        assert!(selector_matches(&selector, &web));
+        assert!(!selector_matches(&selector, &unlabeled));
+
+        let selector = expression("DoesNotExist", &[]);
+        assert!(!selector_matches(&selector, &web));
+        assert!(selector_matches(&selector, &unlabeled));
+    }
+
+    #[test]
+    fn validate_selector_checks_operators_and_values() {
+        assert!(validate_selector(&expression("In", &["web"])).is_ok());
+        assert!(validate_selector(&expression("Exists", &[])).is_ok());
+        assert!(validate_selector(&expression("In", &[])).is_err());
+        assert!(validate_selector(&expression("NotIn", &[])).is_err());
+        assert!(validate_selector(&expression("Exists", &["web"])).is_err());
+        assert!(validate_selector(&expression("DoesNotExist", &["web"])).is_err());
+        let unknown = validate_selector(&expression("Matches", &["web"])).unwrap_err();
+        assert_eq!(unknown, "unknown operator \"Matches\"");
+    }
+
+    #[test]
+    fn empty_match_selects_everything() {
+        let match_resources = MatchResources::default();
+        assert!(match_resources.matches(&target("Deployment", Some("team-a"))));
+        assert!(match_resources.matches(&target("ClusterRole", None)));
+    }
+
+    #[test]
+    fn wildcards_match_any_value() {
+        let match_resources = MatchResources {
+            kinds: vec!["*".to_string()],
+            api_groups: vec!["*".to_string()],
+            namespaces: vec!["*".to_string()],
+            ..MatchResources::default()
+        };
+        assert!(match_resources.matches(&target("Deployment", Some("team-a"))));
+    }
+
+    #[test]
+    fn kinds_and_api_groups_must_be_listed() {
+        let match_resources = MatchResources {
+            kinds: vec!["Deployment".to_string()],
+            api_groups: vec!["apps".to_string()],
+            ..MatchResources::default()
+        };
+        assert!(match_resources.matches(&target("Deployment", Some("team-a"))));
+        assert!(!match_resources.matches(&target("StatefulSet", Some("team-a"))));
+
+        let core = MatchResources {
+            api_groups: vec![String::new()],
+            ..MatchResources::default()
+        };
+        assert!(!core.matches(&target("Deployment", Some("team-a"))));
+    }
+
+    #[test]
+    fn namespaces_exclude_cluster_scoped_targets() {
+        let listed = MatchResources {
+            namespaces: vec!["team-a".to_string()],
+            ..MatchResources::default()
+        };
+        assert!(listed.matches(&target("Deployment", Some("team-a"))));
+        assert!(!listed.matches(&target("Deployment", Some("team-b"))));
+        assert!(!listed.matches(&target("ClusterRole", None)));
+
+        // Even a wildcard only selects namespaced objects.
+        let wildcard = MatchResources {
+            namespaces: vec!["*".to_string()],
+            ..MatchResources::default()
+        };
+        assert!(!wildcard.matches(&target("ClusterRole", None)));
+    }
+
+    #[test]
+    fn label_selector_applies_to_target_labels() {
+        let match_resources = MatchResources {
+            label_selector: Some(selector(json!({ "matchLabels": { "team": "a" } }))),
+            ..MatchResources::default()
+        };
+        assert!(match_resources.matches(&target("Deployment", Some("team-a"))));
+
+        let other = MatchResources {
+            label_selector: Some(selector(json!({ "matchLabels": { "team": "b" } }))),
+            ..MatchResources::default()
+        };
+        assert!(!other.matches(&target("Deployment", Some("team-a"))));
+    }
+
+    fn pattern(value: Value) -> Pattern {
+        Pattern::compile(&serde_json::from_value(value).unwrap()).unwrap()
+    }
+
+    fn failures(pattern: &Pattern, object: &Value) -> Vec<String> {
+        pattern
+            .failures(object)
+            .into_iter()
+            .map(|failure| failure.message)
+            .collect()
+    }
+
+    #[test]
+    fn missing_field_fails_every_check_but_absent() {
+        let object = json!({ "spec": {} });
+        let path = "spec.hostNetwork";
+        let checks = [
+            (json!({ "path": path, "exists": true }), "must be set"),
+            (
+                json!({ "path": path, "equals": "false" }),
+                "must equal \"false\"",
+            ),
+            (
+                json!({ "path": path, "matches": "^f" }),
+                "must match \"^f\"",
+            ),
+            (
+                json!({ "path": path, "oneOf": ["false"] }),
+                "must be one of false",
+            ),
+        ];
+        for (check, message) in checks {
+            assert_eq!(
+                failures(&pattern(check), &object),
+                vec![format!("{path} {message}")]
+            );
+        }
+        let absent = pattern(json!({ "path": path, "absent": true }));
+        assert!(failures(&absent, &object).is_empty());
+    }
+
+    #[test]
+    fn present_field_is_compared_as_text() {
+        let object = json!({ "spec": { "hostNetwork": false, "replicas": 3 } });
+        let passing = [
+            json!({ "path": "spec.hostNetwork", "exists": true }),
+            json!({ "path": "spec.hostNetwork", "equals": "false" }),
+            json!({ "path": "spec.replicas", "matches": "^[1-5]$" }),
+            json!({ "path": "spec.replicas", "oneOf": ["1", "3"] }),
+        ];
+        for check in passing {
+            assert!(
+                failures(&pattern(check.clone()), &object).is_empty(),
+                "{check}"
+            );
+        }
+        let absent = pattern(json!({ "path": "spec.hostNetwork", "absent": true }));
+        assert_eq!(
+            failures(&absent, &object),
+            vec!["spec.hostNetwork must not be set"]
+        );
+    }
+
+    #[test]
+    fn wildcard_failures_name_each_concrete_path() {
+        let object = json!({
+            "spec": { "containers": [{ "image": "nginx:1.27" }, { "image": "nginx" }] }
+        });
+        let tagged = pattern(json!({ "path": "spec.containers[*].image", "matches": ":" }));
+        let found = tagged.failures(&object);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, "spec.containers[1].image");
+
+        let empty = json!({ "spec": { "containers": [] } });
+        assert!(tagged.failures(&empty).is_empty());
+    }
+
+    #[test]
+    fn compile_rejects_bad_patterns() {
+        let no_check = FieldPattern {
+            path: "spec".to_string(),
+            ..FieldPattern::default()
+        };
+        assert!(Pattern::compile(&no_check).is_err());
+        let bad_regex = FieldPattern {
+            path: "spec".to_string(),
+            matches: Some("(".to_string()),
+            ..FieldPattern::default()
+        };
+        assert!(Pattern::compile(&bad_regex).is_err());
+        let bad_path = FieldPattern {
+            path: "spec..name".to_string(),
+            exists: true,
+            ..FieldPattern::default()
+        };
+        assert!(Pattern::compile(&bad_path).is_err());
+    }
 }
@@ -1,11 +1,114 @@
-use super::policies::Policy;
+use serde_json::Value;
+use std::fmt;
 
+use super::policies::Target;
+use crate::crd::{EnforcementAction, FailurePolicy, Severity};
+
+/// Evaluates an object against every rule of a policy.
 pub trait PolicyEvaluator {
-    fn evaluate(&self) -> bool;
+    fn evaluate(&self, object: &Value, context: &EvaluationContext) -> EvaluationResult;
+}
+
+/// What an evaluator knows about an object besides its content.
+pub struct EvaluationContext<'a> {
+    pub target: &'a Target,
+    /// The stored object an UPDATE replaces.
+    pub old_object: Option<&'a Value>,
+    /// The admission request, when evaluating one.
+    pub request: Option<&'a Value>,
+    /// The Namespace the object lives in, when known.
+    pub namespace_object: Option<&'a Value>,
+}
+
+/// The outcome of every rule of one policy for one object.
+pub struct EvaluationResult {
+    pub policy: String,
+    pub enforcement_action: EnforcementAction,
+    pub failure_policy: FailurePolicy,
+    pub rules: Vec<RuleResult>,
+}
+
+pub struct RuleResult {
+    pub policy: String,
+    pub rule: String,
+    pub severity: Severity,
+    pub outcome: Outcome,
+    pub message: String,
+    /// Concrete JSONPaths of the fields that made the rule fail, when known.
+    pub paths: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// The rule does not select the object, has nothing to check, or its
+    /// policy is inactive.
+    Skip,
+    /// The rule could not be evaluated. At admission, the policy's
+    /// failure policy decides whether that denies the request.
+    Error,
+}
+
+impl<'a> EvaluationContext<'a> {
+    pub fn new(target: &'a Target) -> Self {
+        EvaluationContext {
+            target,
+            old_object: None,
+            request: None,
+            namespace_object: None,
+        }
+    }
+}
+
+impl EvaluationResult {
+    pub fn with(&self, outcome: Outcome) -> impl Iterator<Item = &RuleResult> {
+        self.rules
+            .iter()
+            .filter(move |rule| rule.outcome == outcome)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &RuleResult> {
+        self.with(Outcome::Fail)
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &RuleResult> {
+        self.with(Outcome::Error)
+    }
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Pass => "pass",
+            Outcome::Fail => "fail",
+            Outcome::Skip => "skip",
+            Outcome::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
-impl PolicyEvaluator for Policy {
-    fn evaluate(&self) -> bool {
-        self.enabled
+impl fmt::Display for RuleResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}/{} {}] {}",
+            self.policy, self.rule, self.severity, self.message
+        )?;
+        // Generated pattern messages already name each path.
+        if !self
+            .paths
+            .iter()
+            .all(|path| self.message.contains(path.as_str()))
+        {
+            write!(f, " ({})", self.paths.join(", "))?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,63 @@
+use serde_json::Value;
+
+/// JSON pointer to the pod spec embedded in an object of the given kind.
+pub fn pod_spec_pointer(kind: &str) -> Option<&'static str> {
+    match kind {
+        "Pod" => Some("/spec"),
+        "Deployment"
+        | "StatefulSet"
+        | "DaemonSet"
+        | "ReplicaSet"
+        | "ReplicationController"
+        | "Job" => Some("/spec/template/spec"),
+        "CronJob" => Some("/spec/jobTemplate/spec/template/spec"),
+        _ => None,
+    }
+}
+
+pub fn pod_spec_mut<'a>(kind: &str, object: &'a mut Value) -> Option<&'a mut Value> {
+    object.pointer_mut(pod_spec_pointer(kind)?)
+}
+
+/// Regular and init containers of a pod spec. Ephemeral containers are left
+/// out because they cannot declare resources.
+pub fn containers_mut(pod_spec: &mut Value) -> impl Iterator<Item = &mut Value> {
+    let spec = pod_spec.as_object_mut();
+    spec.into_iter().flat_map(|spec| {
+        spec.iter_mut()
+            .filter(|(field, _)| *field == "containers" || *field == "initContainers")
+            .filter_map(|(_, list)| list.as_array_mut())
+            .flatten()
+    })
+}
+
+/// The pod spec embedded in `object`, with its path in JSONPath form.
+pub fn pod_spec<'a>(kind: &str, object: &'a Value) -> Option<(String, &'a Value)> {
+    let pointer = pod_spec_pointer(kind)?;
+    let spec = object.pointer(pointer)?;
+    Some((pointer[1..].replace('/', "."), spec))
+}
+
+/// Every container of a pod spec, including init and ephemeral containers,
+/// with its path below `spec_path`.
+pub fn containers<'a>(
+    spec_path: &'a str,
+    pod_spec: &'a Value,
+) -> impl Iterator<Item = (String, &'a Value)> {
+    ["containers", "initContainers", "ephemeralContainers"]
+        .into_iter()
+        .flat_map(move |field| {
+            pod_spec[field]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .enumerate()
+                .map(move |(index, container)| (format!("{spec_path}.{field}[{index}]"), container))
+        })
+}
+
+/// The metadata of the pod an object runs, for pod template annotations.
+pub fn pod_metadata<'a>(kind: &str, object: &'a Value) -> Option<&'a Value> {
+    let pointer = pod_spec_pointer(kind)?.strip_suffix("/spec")?;
+    object.pointer(&format!("{pointer}/metadata"))
+}
@@ -0,0 +1,235 @@
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+use k8s_openapi::chrono::Utc;
+use kube::Api;
+use kube::api::{ObjectMeta, PostParams};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::config::LeaderElectionConfig;
+use crate::context::Context;
+use crate::shutdown::TaskResult;
+
+/// Runs `lead` only while this replica holds the leader Lease, so a single
+/// replica reconciles and scans at a time. Leadership lost to a failed
+/// renewal stops `lead` and puts the replica back in line; on shutdown
+/// `lead` drains first and the Lease is then released for a quick handover.
+pub async fn run<F, Fut>(ctx: Arc<Context>, shutdown: CancellationToken, lead: F) -> TaskResult
+where
+    F: Fn(Arc<Context>, CancellationToken) -> Fut,
+    Fut: Future<Output = TaskResult> + Send + 'static,
+{
+    let config = ctx.config.leader_election.clone();
+    if !config.enabled {
+        return lead(ctx, shutdown).await;
+    }
+
+    let mut elector = Elector::new(&ctx, &config);
+    info!(
+        "Leader election as {} on Lease {}/{}",
+        elector.identity, config.namespace, config.lease_name
+    );
+    let mut retry = tokio::time::interval(config.retry_period());
+    retry.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        loop {
+            tokio::select! {
+                _ = retry.tick() => {}
+                _ = shutdown.cancelled() => return Ok(()),
+            }
+            match elector.try_acquire_or_renew().await {
+                Ok(true) => break,
+                Ok(false) => debug!("Lease is held by {}", elector.holder()),
+                Err(e) => warn!("Cannot acquire Lease {}: {e}", config.lease_name),
+            }
+        }
+        info!("Became leader");
+
+        // Shutdown cancels `leading` too; the Lease is renewed until `lead`
+        // has drained so no other replica starts while it still writes.
+        let leading = shutdown.child_token();
+        let mut task = tokio::spawn(lead(ctx.clone(), leading.clone()));
+        let mut renewed = Instant::now();
+        let mut holding = true;
+        let joined = loop {
+            tokio::select! {
+                joined = &mut task => break joined,
+                _ = retry.tick(), if holding => {}
+            }
+            match elector.try_acquire_or_renew().await {
+                Ok(true) => renewed = Instant::now(),
+                Ok(false) => {
+                    warn!("Lost the Lease to {}, stepping down", elector.holder());
+                    holding = false;
+                }
+                Err(e) if renewed.elapsed() < config.renew_deadline() => {
+                    warn!("Cannot renew Lease {}: {e}", config.lease_name);
+                }
+                Err(e) => {
+                    warn!(
+                        "Cannot renew Lease {} within {}s, stepping down: {e}",
+                        config.lease_name, config.renew_deadline_secs
+                    );
+                    holding = false;
+                }
+            }
+            if !holding {
+                leading.cancel();
+            }
+        };
+
+        if holding {
+            // Shutting down, or the leader's work ended on its own: either
+            // way the next replica can take over right away.
+            elector.release().await;
+            return joined?;
+        }
+        joined??;
+        info!("Stopped leading, waiting for the Lease");
+    }
+}
+
+/// Acquires and renews the Lease with optimistic concurrency: every write
+/// carries the resourceVersion it was based on, so of two replicas racing
+/// for an expired Lease only one succeeds.
+struct Elector {
+    api: Api<Lease>,
+    name: String,
+    identity: String,
+    lease_duration: Duration,
+    /// The last holder and renewal seen, and when this replica first saw
+    /// them. Expiry is judged by the local clock from that moment rather
+    /// than by comparing timestamps written by another node.
+    observed: Option<(Option<String>, Option<MicroTime>, Instant)>,
+}
+
+impl Elector {
+    fn new(ctx: &Context, config: &LeaderElectionConfig) -> Self {
+        Elector {
+            api: Api::namespaced(ctx.client.clone(), &config.namespace),
+            name: config.lease_name.clone(),
+            identity: config.identity(),
+            lease_duration: config.lease_duration(),
+            observed: None,
+        }
+    }
+
+    /// Whether this replica holds the Lease after the attempt.
+    async fn try_acquire_or_renew(&mut self) -> Result<bool, kube::Error> {
+        let now = MicroTime(Utc::now());
+        let Some(mut lease) = self.api.get_opt(&self.name).await? else {
+            let lease = Lease {
+                metadata: ObjectMeta {
+                    name: Some(self.name.clone()),
+                    ..ObjectMeta::default()
+                },
+                spec: Some(self.spec(now.clone(), now, 0)),
+            };
+            return self.write(self.api.create(&PostParams::default(), &lease).await);
+        };
+
+        let current = lease.spec.take().unwrap_or_default();
+        let holder = current.holder_identity.filter(|holder| !holder.is_empty());
+        self.observe(holder.clone(), current.renew_time.clone());
+        let transitions = current.lease_transitions.unwrap_or_default();
+        let spec = if holder.as_deref() == Some(&self.identity) {
+            let acquired = current.acquire_time.unwrap_or_else(|| now.clone());
+            self.spec(acquired, now, transitions)
+        } else {
+            let duration = current
+                .lease_duration_seconds
+                .map_or(self.lease_duration, |secs| {
+                    Duration::from_secs(secs.max(0) as u64)
+                });
+            if holder.is_some() && !self.expired(duration) {
+                return Ok(false);
+            }
+            self.spec(now.clone(), now, transitions + 1)
+        };
+        lease.spec = Some(spec);
+        self.write(
+            self.api
+                .replace(&self.name, &PostParams::default(), &lease)
+                .await,
+        )
+    }
+
+    /// Gives up the Lease if this replica still holds it.
+    async fn release(&self) {
+        let released = async {
+            let mut lease = self.api.get(&self.name).await?;
+            let Some(spec) = lease.spec.as_mut() else {
+                return Ok(());
+            };
+            if spec.holder_identity.as_deref() != Some(&self.identity) {
+                return Ok(());
+            }
+            spec.holder_identity = None;
+            spec.lease_duration_seconds = Some(1);
+            spec.renew_time = Some(MicroTime(Utc::now()));
+            self.api
+                .replace(&self.name, &PostParams::default(), &lease)
+                .await
+                .map(|_| ())
+        };
+        match released.await {
+            Ok(()) => info!("Released Lease {}", self.name),
+            Err(e) => warn!(
+                "Cannot release Lease {}, another replica takes over once it expires: {e}",
+                self.name
+            ),
+        }
+    }
+
+    fn spec(&self, acquired: MicroTime, renewed: MicroTime, transitions: i32) -> LeaseSpec {
+        LeaseSpec {
+            holder_identity: Some(self.identity.clone()),
+            lease_duration_seconds: Some(self.lease_duration.as_secs() as i32),
+            acquire_time: Some(acquired),
+            renew_time: Some(renewed),
+            lease_transitions: Some(transitions),
+            ..LeaseSpec::default()
+        }
+    }
+
+    /// A write lost to a concurrent update means another replica got there first.
+    fn write(&mut self, result: Result<Lease, kube::Error>) -> Result<bool, kube::Error> {
+        match result {
+            Ok(lease) => {
+                let renewed = lease.spec.and_then(|spec| spec.renew_time);
+                self.observe(Some(self.identity.clone()), renewed);
+                Ok(true)
+            }
+            Err(kube::Error::Api(response)) if response.code == 409 => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn observe(&mut self, holder: Option<String>, renewed: Option<MicroTime>) {
+        let unchanged = self
+            .observed
+            .as_ref()
+            .is_some_and(|(seen, at, _)| *seen == holder && *at == renewed);
+        if !unchanged {
+            self.observed = Some((holder, renewed, Instant::now()));
+        }
+    }
+
+    fn expired(&self, duration: Duration) -> bool {
+        self.observed
+            .as_ref()
+            .is_none_or(|(_, _, since)| since.elapsed() >= duration)
+    }
+
+    fn holder(&self) -> &str {
+        self.observed
+            .as_ref()
+            .and_then(|(holder, _, _)| holder.as_deref())
+            .unwrap_or("nobody")
+    }
+}
@@ -1,20 +1,81 @@
+mod certs;
 mod cli;
-#[allow(dead_code)]
+mod config;
+mod context;
 mod controller;
 mod crd;
-#[allow(dead_code)]
+mod error;
+mod evaluate;
+mod events;
 mod governance;
+mod leader;
+mod metrics;
 mod reconcile;
-#[allow(dead_code)]
+mod reports;
+mod shutdown;
+mod tls;
 mod webhook;
 
 use clap::Parser;
-use cli::Cli;
+use cli::{Cli, Command, CrdCommand};
+use config::Config;
+use context::Context;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // axum-server is built without a crypto provider, so the webhook's TLS
+    // uses ring like the kube client does.
+    rustls::crypto::ring::default_provider()
+        .install_default()
+        .map_err(|_| "Cannot install the rustls crypto provider")?;
+
     let cli = Cli::parse();
-    println!("Loaded config: {}", cli.config);
-    println!("Async runtime initialized");
+    let config = Config::load(cli.config.as_deref(), &cli.overrides)?;
+
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(&config.log_level))
+        .init();
+
+    match cli.command {
+        Command::Run | Command::Controller | Command::Webhook => {
+            let token = CancellationToken::new();
+            let mut tasks = JoinSet::new();
+
+            let client = kube::Client::try_default().await?;
+            let ctx = Context::new(client, config.clone());
+
+            if matches!(cli.command, Command::Run | Command::Controller) {
+                tasks.spawn(leader::run(
+                    ctx.clone(),
+                    token.clone(),
+                    controller::run_controller,
+                ));
+            }
+            if config.metrics.enabled {
+                tasks.spawn(metrics::serve(ctx.clone(), token.clone()));
+            }
+            if matches!(cli.command, Command::Run | Command::Webhook) {
+                tasks.spawn(webhook::serve(ctx, token.clone()));
+            }
+
+            let drain_timeout = config.shutdown.drain_timeout();
+            shutdown::run_until_shutdown(tasks, token, drain_timeout)
+                .await
+                .map_err(|e| e as Box<dyn std::error::Error>)?;
+        }
+        Command::Evaluate { policy, resources } => evaluate::run(&policy, &resources)?,
+        Command::Crd { action } => match action {
+            CrdCommand::Print { output } => crd::print(output)?,
+            CrdCommand::Install { timeout } => {
+                let client = kube::Client::try_default().await?;
+                crd::install(client, Duration::from_secs(timeout)).await?;
+            }
+        },
+        Command::Version => println!("kube-guardian {}", env!("CARGO_PKG_VERSION")),
+    }
     Ok(())
 }
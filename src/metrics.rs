@@ -0,0 +1,205 @@
+use axum::Router;
+use axum::extract::State;
+use axum::http::{StatusCode, header};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use kube::api::DynamicObject;
+use kube::core::admission::{AdmissionRequest, Operation};
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::encoding::text::encode;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{Histogram, exponential_buckets};
+use prometheus_client::registry::{Registry, Unit};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::context::Context;
+use crate::shutdown::TaskResult;
+
+/// Metric handles shared by every component, and the registry that exposes
+/// them with the `kube_guardian_` prefix.
+pub struct Metrics {
+    registry: Registry,
+    pub reconciliations: Family<PolicyLabels, Counter>,
+    pub reconcile_errors: Family<ErrorLabels, Counter>,
+    pub reconcile_duration: Family<PolicyLabels, Histogram, fn() -> Histogram>,
+    pub compile_failures: Family<PolicyLabels, Counter>,
+    pub admission_requests: Family<AdmissionLabels, Counter>,
+    pub admission_duration: Family<EndpointLabels, Histogram, fn() -> Histogram>,
+    /// Resources the last background scan found violating each policy.
+    pub violations: Family<ViolationLabels, Gauge>,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct PolicyLabels {
+    pub policy: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ErrorLabels {
+    /// `invalid_policy`, `conflict`, `api` or `evaluation`.
+    pub kind: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct AdmissionLabels {
+    /// `validate` or `mutate`.
+    pub endpoint: &'static str,
+    /// `CREATE`, `UPDATE`, `DELETE` or `CONNECT`; empty for malformed reviews.
+    pub operation: &'static str,
+    pub kind: String,
+    /// `allowed`, `denied`, `patched` or `invalid`.
+    pub decision: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct EndpointLabels {
+    pub endpoint: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ViolationLabels {
+    pub policy: String,
+    pub severity: &'static str,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let mut metrics = Metrics {
+            registry: Registry::with_prefix("kube_guardian"),
+            reconciliations: Family::default(),
+            reconcile_errors: Family::default(),
+            reconcile_duration: Family::new_with_constructor(reconcile_histogram),
+            compile_failures: Family::default(),
+            admission_requests: Family::default(),
+            admission_duration: Family::new_with_constructor(admission_histogram),
+            violations: Family::default(),
+        };
+        let registry = &mut metrics.registry;
+        registry.register(
+            "reconciliations",
+            "GuardianPolicy reconciliations",
+            metrics.reconciliations.clone(),
+        );
+        registry.register(
+            "reconcile_errors",
+            "Failed reconciliations by kind of error",
+            metrics.reconcile_errors.clone(),
+        );
+        registry.register_with_unit(
+            "reconcile_duration",
+            "Time taken to reconcile a GuardianPolicy",
+            Unit::Seconds,
+            metrics.reconcile_duration.clone(),
+        );
+        registry.register(
+            "policy_compile_failures",
+            "GuardianPolicy generations whose rules failed to compile",
+            metrics.compile_failures.clone(),
+        );
+        registry.register(
+            "admission_requests",
+            "Admission reviews answered",
+            metrics.admission_requests.clone(),
+        );
+        registry.register_with_unit(
+            "admission_duration",
+            "Time taken to answer an admission review",
+            Unit::Seconds,
+            metrics.admission_duration.clone(),
+        );
+        registry.register(
+            "violating_resources",
+            "Resources in violation as of the last background scan",
+            metrics.violations.clone(),
+        );
+        metrics
+    }
+}
+
+impl Metrics {
+    /// Counts an admission review answered since `started`.
+    pub fn admission(
+        &self,
+        endpoint: &'static str,
+        request: Option<&AdmissionRequest<DynamicObject>>,
+        decision: &'static str,
+        started: Instant,
+    ) {
+        let labels = AdmissionLabels {
+            endpoint,
+            operation: request.map_or("", |request| operation(&request.operation)),
+            kind: request.map_or_else(String::new, |request| request.kind.kind.clone()),
+            decision,
+        };
+        self.admission_requests.get_or_create(&labels).inc();
+        self.admission_duration
+            .get_or_create(&EndpointLabels { endpoint })
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    /// Drops the series of a deleted policy.
+    pub fn forget(&self, policy: &str) {
+        let labels = PolicyLabels {
+            policy: policy.to_string(),
+        };
+        self.reconciliations.remove(&labels);
+        self.reconcile_duration.remove(&labels);
+        self.compile_failures.remove(&labels);
+    }
+
+    fn encode(&self) -> Result<String, std::fmt::Error> {
+        let mut body = String::new();
+        encode(&mut body, &self.registry)?;
+        Ok(body)
+    }
+}
+
+/// Serves `/metrics` on its own plain HTTP listener until `shutdown` is
+/// cancelled, so scraping works without the webhook's client certificates.
+pub async fn serve(ctx: Arc<Context>, shutdown: CancellationToken) -> TaskResult {
+    let addr = ctx.config.metrics.bind_address;
+    let app = Router::new().route("/metrics", get(scrape)).with_state(ctx);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Metrics listening on http://{addr}/metrics");
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await?;
+    Ok(())
+}
+
+async fn scrape(State(ctx): State<Arc<Context>>) -> impl IntoResponse {
+    match ctx.metrics.encode() {
+        Ok(body) => (
+            StatusCode::OK,
+            [(
+                header::CONTENT_TYPE,
+                "application/openmetrics-text; version=1.0.0; charset=utf-8",
+            )],
+            body,
+        )
+            .into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+fn operation(operation: &Operation) -> &'static str {
+    match operation {
+        Operation::Create => "CREATE",
+        Operation::Update => "UPDATE",
+        Operation::Delete => "DELETE",
+        Operation::Connect => "CONNECT",
+    }
+}
+
+fn reconcile_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.01, 2.0, 12))
+}
+
+fn admission_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.0005, 2.0, 14))
+}
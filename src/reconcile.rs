@@ -1,14 +1,209 @@
-use kube::ResourceExt;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+use k8s_openapi::chrono::Utc;
+use kube::api::{Patch, PatchParams};
 use kube::runtime::controller::Action;
+use kube::runtime::finalizer::{Event, finalizer};
+use kube::{Api, Resource, ResourceExt};
+use serde_json::json;
 use std::sync::Arc;
+use std::time::Instant;
 
-use crate::crd::GuardianPolicy;
+use tracing::{info, warn};
 
-pub async fn reconcile(policy: Arc<GuardianPolicy>, _ctx: Arc<()>) -> Result<Action, kube::Error> {
-    println!("Reconciling: {}", policy.name_any());
-    Ok(Action::requeue(std::time::Duration::from_secs(300)))
+use crate::context::Context;
+use crate::crd::{FIELD_MANAGER, GuardianPolicy, GuardianPolicyStatus};
+use crate::error::{Error, Result};
+use crate::metrics::{ErrorLabels, PolicyLabels};
+use crate::reports;
+
+/// Held by every GuardianPolicy until its reports and events are cleaned up.
+pub const FINALIZER: &str = "guardian.io/cleanup";
+
+pub async fn reconcile(policy: Arc<GuardianPolicy>, ctx: Arc<Context>) -> Result<Action> {
+    let name = policy.name_any();
+    let labels = PolicyLabels {
+        policy: name.clone(),
+    };
+    ctx.metrics.reconciliations.get_or_create(&labels).inc();
+    let started = Instant::now();
+    let deleting = policy.meta().deletion_timestamp.is_some();
+    let api: Api<GuardianPolicy> = Api::all(ctx.client.clone());
+    let result = finalizer(&api, FINALIZER, policy, |event| async {
+        match event {
+            Event::Apply(policy) => apply(policy, &ctx).await,
+            Event::Cleanup(policy) => cleanup(policy, &ctx).await,
+        }
+    })
+    .await
+    .map_err(Error::from);
+    ctx.metrics
+        .reconcile_duration
+        .get_or_create(&labels)
+        .observe(started.elapsed().as_secs_f64());
+    if result.is_ok() {
+        ctx.attempts.succeeded(&name);
+        if deleting {
+            ctx.metrics.forget(&name);
+        }
+    }
+    result
 }
 
-pub fn error_policy(_obj: Arc<GuardianPolicy>, _error: &kube::Error, _ctx: Arc<()>) -> Action {
-    Action::requeue(std::time::Duration::from_secs(60))
+async fn apply(policy: Arc<GuardianPolicy>, ctx: &Context) -> Result<Action> {
+    info!("Reconciling: {}", policy.name_any());
+
+    // Compiling here means the webhook finds the policy ready to evaluate.
+    let compiled = ctx.policies.get(&policy);
+    let governed = &compiled.policy;
+    let validation = compiled.validation().clone();
+    match &validation {
+        Ok(()) => info!(
+            "Policy {} has {} valid rule(s)",
+            governed.name,
+            governed.rules.len()
+        ),
+        Err((reason, e)) => {
+            warn!("Policy {} is invalid: {}", governed.name, e);
+            if *reason == "CompileError" {
+                ctx.metrics
+                    .compile_failures
+                    .get_or_create(&PolicyLabels {
+                        policy: governed.name.clone(),
+                    })
+                    .inc();
+            }
+        }
+    }
+
+    let status = desired_status(&policy, &validation);
+    let api: Api<GuardianPolicy> = Api::all(ctx.client.clone());
+    let patch = json!({
+        "apiVersion": "guardian.io/v1",
+        "kind": "GuardianPolicy",
+        "status": status,
+    });
+    api.patch_status(
+        &policy.name_any(),
+        &PatchParams::apply(FIELD_MANAGER).force(),
+        &Patch::Apply(&patch),
+    )
+    .await?;
+
+    // The generation is part of the note so every new spec gets an event,
+    // while requeues of an unchanged policy are deduplicated.
+    let generation = policy.metadata.generation.unwrap_or_default();
+    let regarding = policy.object_ref(&());
+    match &validation {
+        Ok(()) => {
+            let note = format!(
+                "Generation {generation} is ready with {} rule(s)",
+                governed.rules.len()
+            );
+            ctx.recorder
+                .normal(&regarding, "PolicyReady", "Reconcile", &note)
+                .await;
+        }
+        Err((reason, e)) => {
+            let note = format!("Generation {generation} is invalid ({reason}): {e}");
+            ctx.recorder
+                .warning(&regarding, "PolicyInvalid", "Reconcile", &note)
+                .await;
+        }
+    }
+
+    match validation {
+        Ok(()) => Ok(Action::requeue(ctx.config.controller.requeue_interval())),
+        Err((reason, message)) => Err(Error::InvalidPolicy {
+            policy: governed.name.clone(),
+            reason,
+            message,
+        }),
+    }
+}
+
+/// Removes what kube-guardian created for a policy being deleted. The
+/// finalizer is only released once this succeeds, so cleanup interrupted by a
+/// restart is retried.
+async fn cleanup(policy: Arc<GuardianPolicy>, ctx: &Context) -> Result<Action> {
+    let name = policy.name_any();
+    info!("Cleaning up after {name}");
+    reports::delete_policy(&ctx.client, &name).await?;
+    if let Some(uid) = policy.uid() {
+        ctx.recorder.delete_cluster_events(&uid).await?;
+    }
+    ctx.recorder.delete_violation_events(&name).await?;
+    Ok(Action::await_change())
+}
+
+/// Picks the retry from the kind of failure and how often the policy has
+/// failed in a row.
+pub fn error_policy(policy: Arc<GuardianPolicy>, error: &Error, ctx: Arc<Context>) -> Action {
+    ctx.metrics
+        .reconcile_errors
+        .get_or_create(&ErrorLabels { kind: error.kind() })
+        .inc();
+    let name = policy.name_any();
+    let attempt = ctx.attempts.failed(&name);
+    let max = ctx.config.controller.error_requeue_interval();
+    match error.retry_after(attempt, max) {
+        Some(delay) => {
+            info!("Retrying {name} in {delay:?} after {attempt} failed attempt(s)");
+            Action::requeue(delay)
+        }
+        None => {
+            info!("Not retrying {name} until the policy changes");
+            Action::await_change()
+        }
+    }
+}
+
+fn desired_status(
+    policy: &GuardianPolicy,
+    validation: &Result<(), (&str, String)>,
+) -> GuardianPolicyStatus {
+    let current = policy.status.clone().unwrap_or_default();
+    let generation = policy.metadata.generation;
+
+    let (ready, degraded) = match validation {
+        Ok(()) => (
+            ("True", "Valid", "Policy rules are valid".to_string()),
+            ("False", "Valid", "Policy rules are valid".to_string()),
+        ),
+        Err((reason, e)) => (("False", *reason, e.clone()), ("True", *reason, e.clone())),
+    };
+
+    GuardianPolicyStatus {
+        conditions: vec![
+            condition(&current.conditions, "Ready", ready, generation),
+            condition(&current.conditions, "Degraded", degraded, generation),
+        ],
+        observed_generation: generation,
+        last_evaluation_time: Some(Time(Utc::now())),
+        // Left to the background scanner.
+        compliant_resources: None,
+        violating_resources: None,
+    }
+}
+
+/// Builds a condition, keeping the previous transition time if the status is unchanged.
+fn condition(
+    existing: &[Condition],
+    type_: &str,
+    (status, reason, message): (&str, &str, String),
+    generation: Option<i64>,
+) -> Condition {
+    let last_transition_time = existing
+        .iter()
+        .find(|c| c.type_ == type_ && c.status == status)
+        .map(|c| c.last_transition_time.clone())
+        .unwrap_or_else(|| Time(Utc::now()));
+
+    Condition {
+        type_: type_.to_string(),
+        status: status.to_string(),
+        reason: reason.to_string(),
+        message,
+        observed_generation: generation,
+        last_transition_time,
+    }
 }
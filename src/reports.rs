@@ -0,0 +1,556 @@
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::chrono::Utc;
+use kube::api::{
+    ApiResource, DeleteParams, DynamicObject, GroupVersionKind, ListParams, Patch, PatchParams,
+};
+use kube::{Api, Client, ResourceExt};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use tracing::{debug, info, warn};
+
+use crate::crd::{EnforcementAction, FIELD_MANAGER, Severity};
+use crate::governance::traits::{Outcome, RuleResult};
+
+/// API group of the Policy Report CRDs from the Kubernetes Policy working group.
+pub const GROUP: &str = "wgpolicyk8s.io";
+const VERSION: &str = "v1alpha2";
+const SOURCE: &str = "kube-guardian";
+const MANAGED_BY: &str = "app.kubernetes.io/managed-by=kube-guardian";
+const NAME_PREFIX: &str = "guardian-";
+
+/// A report holds the results of one GuardianPolicy: a PolicyReport per
+/// namespace, and a ClusterPolicyReport for cluster-scoped resources.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ReportKey {
+    pub namespace: Option<String>,
+    pub policy: String,
+}
+
+/// Identifies a result within a report: the rule and the resource UID.
+pub type ResultKey = (String, String);
+
+/// Results of one scan, by report.
+pub type Desired = BTreeMap<ReportKey, BTreeMap<ResultKey, ReportResult>>;
+
+/// Results of one report, each with the time it first appeared.
+type Results = BTreeMap<ResultKey, (ReportResult, Time)>;
+
+/// What was last written to one report.
+#[derive(Default)]
+struct Written {
+    results: Results,
+    /// Result keys in the order of the report's `results` list.
+    order: Vec<ResultKey>,
+    /// The report's resourceVersion after the write, when known.
+    resource_version: Option<String>,
+}
+
+/// One rule evaluated against one resource, in Policy Report form.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportResult {
+    pub policy: String,
+    pub rule: String,
+    pub result: &'static str,
+    pub scored: bool,
+    pub severity: &'static str,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub message: String,
+    pub source: &'static str,
+    pub resources: Vec<ResourceRef>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceRef {
+    pub api_version: String,
+    pub kind: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    pub uid: String,
+}
+
+/// Writes scan results as PolicyReports and ClusterPolicyReports, remembering
+/// what it wrote so that only the results that changed are sent.
+pub struct Reports {
+    client: Client,
+    /// Results last written per report.
+    written: HashMap<ReportKey, Written>,
+}
+
+impl ReportResult {
+    /// The report entry for a rule result, or `None` for skipped rules, which
+    /// reports leave out.
+    pub fn new(
+        result: &RuleResult,
+        action: EnforcementAction,
+        resource: ResourceRef,
+    ) -> Option<ReportResult> {
+        let outcome = match (result.outcome, action) {
+            (Outcome::Pass, _) => "pass",
+            // Violations the policy lets through are reported as warnings.
+            (Outcome::Fail, EnforcementAction::Warn) => "warn",
+            (Outcome::Fail, _) => "fail",
+            (Outcome::Error, _) => "error",
+            (Outcome::Skip, _) => return None,
+        };
+        let mut properties = BTreeMap::new();
+        properties.insert("enforcementAction".to_string(), action.to_string());
+        if !result.paths.is_empty() {
+            properties.insert("paths".to_string(), result.paths.join(", "));
+        }
+        Some(ReportResult {
+            policy: result.policy.clone(),
+            rule: result.rule.clone(),
+            result: outcome,
+            scored: true,
+            severity: severity(result.severity),
+            message: result.message.clone(),
+            source: SOURCE,
+            resources: vec![resource],
+            properties,
+        })
+    }
+}
+
+impl Reports {
+    pub fn new(client: Client) -> Self {
+        Reports {
+            client,
+            written: HashMap::new(),
+        }
+    }
+
+    /// Remembers the reports a previous run left behind, so they are
+    /// rewritten or deleted by the next [`Reports::sync`].
+    pub async fn load(&mut self) -> Result<(), kube::Error> {
+        let params = ListParams::default().labels(MANAGED_BY);
+        for kind in ["PolicyReport", "ClusterPolicyReport"] {
+            for report in api(&self.client, kind, None).list(&params).await? {
+                let name = report.name_any();
+                let Some(policy) = name.strip_prefix(NAME_PREFIX) else {
+                    continue;
+                };
+                let key = ReportKey {
+                    namespace: report.namespace(),
+                    policy: policy.to_string(),
+                };
+                self.written.entry(key).or_default();
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes every report whose results differ from what was last written
+    /// and deletes reports that no longer have results. `owners` maps policy
+    /// names to UIDs, so reports are garbage collected with their policy.
+    pub async fn sync(&mut self, desired: Desired, owners: &HashMap<String, String>) {
+        let now = Time(Utc::now());
+        let (mut applied, mut deleted) = (0, 0);
+        let stale: Vec<ReportKey> = self
+            .written
+            .keys()
+            .filter(|key| !desired.contains_key(*key))
+            .cloned()
+            .collect();
+
+        for (key, results) in desired {
+            let previous = self.written.get(&key);
+            let results: Results = results
+                .into_iter()
+                .map(|(id, result)| {
+                    // A result keeps its timestamp for as long as it is unchanged.
+                    let since = previous
+                        .and_then(|written| written.results.get(&id))
+                        .filter(|(written, _)| *written == result)
+                        .map(|(_, since)| since.clone())
+                        .unwrap_or_else(|| now.clone());
+                    (id, (result, since))
+                })
+                .collect();
+            if previous.is_some_and(|written| written.results == results) {
+                continue;
+            }
+            let Some(uid) = owners.get(&key.policy) else {
+                continue;
+            };
+            let written = match previous.and_then(|written| changes(written, &results)) {
+                Some((operations, order)) => match self.patch(&key, operations).await {
+                    Ok(resource_version) => Ok(Written {
+                        results,
+                        order,
+                        resource_version,
+                    }),
+                    // Changed by someone else or gone: write it whole.
+                    Err(e) => {
+                        debug!("Cannot patch {}, applying it whole: {e}", describe(&key));
+                        self.apply(&key, uid, results).await
+                    }
+                },
+                None => self.apply(&key, uid, results).await,
+            };
+            match written {
+                Ok(written) => {
+                    applied += 1;
+                    self.written.insert(key, written);
+                }
+                Err(e) => warn!("Cannot write {}: {e}", describe(&key)),
+            }
+        }
+
+        for key in stale {
+            match self.delete(&key).await {
+                Ok(()) => {
+                    deleted += 1;
+                    self.written.remove(&key);
+                }
+                Err(e) => warn!("Cannot delete {}: {e}", describe(&key)),
+            }
+        }
+        if applied + deleted > 0 {
+            info!("Updated {applied} and deleted {deleted} policy report(s)");
+        }
+    }
+
+    /// Server-side applies the whole report.
+    async fn apply(
+        &self,
+        key: &ReportKey,
+        uid: &str,
+        results: Results,
+    ) -> Result<Written, kube::Error> {
+        let kind = report_kind(key);
+        let name = report_name(&key.policy);
+        let report = json!({
+            "apiVersion": format!("{GROUP}/{VERSION}"),
+            "kind": kind,
+            "metadata": {
+                "name": name,
+                "labels": { "app.kubernetes.io/managed-by": "kube-guardian" },
+                "ownerReferences": [{
+                    "apiVersion": "guardian.io/v1",
+                    "kind": "GuardianPolicy",
+                    "name": key.policy,
+                    "uid": uid,
+                }],
+            },
+            "summary": summary(&results),
+            "results": results.values().map(entry).collect::<Vec<_>>(),
+        });
+        let report = api(&self.client, kind, key.namespace.as_deref())
+            .patch(
+                &name,
+                &PatchParams::apply(FIELD_MANAGER).force(),
+                &Patch::Apply(&report),
+            )
+            .await?;
+        debug!("Wrote {}", describe(key));
+        Ok(Written {
+            order: results.keys().cloned().collect(),
+            results,
+            resource_version: report.resource_version(),
+        })
+    }
+
+    /// Applies the JSON patch `operations` from [`changes`] and returns the
+    /// new resourceVersion.
+    async fn patch(
+        &self,
+        key: &ReportKey,
+        operations: Vec<Value>,
+    ) -> Result<Option<String>, kube::Error> {
+        let patch: json_patch::Patch =
+            serde_json::from_value(Value::Array(operations)).map_err(kube::Error::SerdeError)?;
+        let params = PatchParams {
+            field_manager: Some(FIELD_MANAGER.to_string()),
+            ..PatchParams::default()
+        };
+        let report = api(&self.client, report_kind(key), key.namespace.as_deref())
+            .patch(
+                &report_name(&key.policy),
+                &params,
+                &Patch::Json::<()>(patch),
+            )
+            .await?;
+        debug!("Patched {}", describe(key));
+        Ok(report.resource_version())
+    }
+
+    async fn delete(&self, key: &ReportKey) -> Result<(), kube::Error> {
+        delete(&self.client, key).await
+    }
+}
+
+/// Deletes every report of `policy`. Succeeds when the Policy Report CRDs are
+/// not installed.
+pub async fn delete_policy(client: &Client, policy: &str) -> Result<(), kube::Error> {
+    let name = report_name(policy);
+    let params = ListParams::default().labels(MANAGED_BY);
+    let namespaced = match api(client, "PolicyReport", None).list(&params).await {
+        Ok(reports) => reports.items,
+        Err(kube::Error::Api(e)) if e.code == 404 => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let mut keys: Vec<ReportKey> = namespaced
+        .iter()
+        .filter(|report| report.name_any() == name)
+        .map(|report| ReportKey {
+            namespace: report.namespace(),
+            policy: policy.to_string(),
+        })
+        .collect();
+    keys.push(ReportKey {
+        namespace: None,
+        policy: policy.to_string(),
+    });
+    for key in &keys {
+        delete(client, key).await?;
+    }
+    Ok(())
+}
+
+async fn delete(client: &Client, key: &ReportKey) -> Result<(), kube::Error> {
+    let api = api(client, report_kind(key), key.namespace.as_deref());
+    match api
+        .delete(&report_name(&key.policy), &DeleteParams::default())
+        .await
+    {
+        Ok(_) => Ok(()),
+        // Already gone, e.g. collected along with its namespace or policy.
+        Err(kube::Error::Api(e)) if e.code == 404 => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// The JSON patch operations that turn the report last `written` into one
+/// holding `results`, and the order of its results afterwards. Only changed,
+/// removed and new results are sent; the patch fails unless the report is
+/// still at the version written last. `None` when that version is unknown.
+fn changes(written: &Written, results: &Results) -> Option<(Vec<Value>, Vec<ResultKey>)> {
+    let resource_version = written.resource_version.as_ref()?;
+    let mut operations = vec![json!({
+        "op": "test",
+        "path": "/metadata/resourceVersion",
+        "value": resource_version,
+    })];
+    for (index, id) in written.order.iter().enumerate() {
+        if let Some(result) = results.get(id)
+            && written.results.get(id) != Some(result)
+        {
+            operations.push(json!({
+                "op": "replace",
+                "path": format!("/results/{index}"),
+                "value": entry(result),
+            }));
+        }
+    }
+    // Back to front, so earlier indexes stay valid.
+    for (index, id) in written.order.iter().enumerate().rev() {
+        if !results.contains_key(id) {
+            operations.push(json!({ "op": "remove", "path": format!("/results/{index}") }));
+        }
+    }
+    let mut order: Vec<ResultKey> = written
+        .order
+        .iter()
+        .filter(|id| results.contains_key(*id))
+        .cloned()
+        .collect();
+    for (id, result) in results {
+        if !written.results.contains_key(id) {
+            operations.push(json!({ "op": "add", "path": "/results/-", "value": entry(result) }));
+            order.push(id.clone());
+        }
+    }
+    // `add` replaces the summary, or sets it if someone removed it.
+    operations.push(json!({ "op": "add", "path": "/summary", "value": summary(results) }));
+    Some((operations, order))
+}
+
+/// A report's `results` entry.
+fn entry((result, since): &(ReportResult, Time)) -> Value {
+    let mut entry = serde_json::to_value(result).unwrap_or_default();
+    entry["timestamp"] = json!({ "seconds": since.0.timestamp(), "nanos": 0 });
+    entry
+}
+
+/// A report's `summary`: how many results have each outcome.
+fn summary(results: &Results) -> Value {
+    let mut summary = BTreeMap::from([
+        ("pass", 0),
+        ("fail", 0),
+        ("warn", 0),
+        ("error", 0),
+        ("skip", 0),
+    ]);
+    for (result, _) in results.values() {
+        *summary.entry(result.result).or_default() += 1;
+    }
+    json!(summary)
+}
+
+/// Severity as written to policy reports and metrics.
+pub fn severity(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Info => "info",
+        Severity::Low => "low",
+        Severity::Medium => "medium",
+        Severity::High => "high",
+        Severity::Critical => "critical",
+    }
+}
+
+fn report_kind(key: &ReportKey) -> &'static str {
+    match key.namespace {
+        Some(_) => "PolicyReport",
+        None => "ClusterPolicyReport",
+    }
+}
+
+fn report_name(policy: &str) -> String {
+    format!("{NAME_PREFIX}{policy}")
+}
+
+fn describe(key: &ReportKey) -> String {
+    match &key.namespace {
+        Some(ns) => format!("PolicyReport {ns}/{}", report_name(&key.policy)),
+        None => format!("ClusterPolicyReport {}", report_name(&key.policy)),
+    }
+}
+
+fn api(client: &Client, kind: &str, namespace: Option<&str>) -> Api<DynamicObject> {
+    let resource = ApiResource::from_gvk(&GroupVersionKind::gvk(GROUP, VERSION, kind));
+    match namespace {
+        Some(ns) => Api::namespaced_with(client.clone(), ns, &resource),
+        None => Api::all_with(client.clone(), &resource),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::chrono::DateTime;
+
+    fn result(uid: &str, outcome: &'static str) -> (ResultKey, (ReportResult, Time)) {
+        let result = ReportResult {
+            policy: "p".to_string(),
+            rule: "r".to_string(),
+            result: outcome,
+            scored: true,
+            severity: "low",
+            message: String::new(),
+            source: SOURCE,
+            resources: vec![ResourceRef {
+                api_version: "v1".to_string(),
+                kind: "Pod".to_string(),
+                name: uid.to_string(),
+                namespace: Some("default".to_string()),
+                uid: uid.to_string(),
+            }],
+            properties: BTreeMap::new(),
+        };
+        let since = Time(DateTime::from_timestamp(1_700_000_000, 0).unwrap());
+        (("r".to_string(), uid.to_string()), (result, since))
+    }
+
+    fn results(entries: &[(&str, &'static str)]) -> Results {
+        entries
+            .iter()
+            .map(|(uid, outcome)| result(uid, outcome))
+            .collect()
+    }
+
+    fn written(entries: &[(&str, &'static str)], order: &[&str]) -> Written {
+        Written {
+            results: results(entries),
+            order: order
+                .iter()
+                .map(|uid| ("r".to_string(), uid.to_string()))
+                .collect(),
+            resource_version: Some("42".to_string()),
+        }
+    }
+
+    fn uids(order: &[ResultKey]) -> Vec<&str> {
+        order.iter().map(|(_, uid)| uid.as_str()).collect()
+    }
+
+    fn ops(operations: &[Value]) -> Vec<(String, String)> {
+        operations
+            .iter()
+            .map(|op| {
+                let op_name = op["op"].as_str().unwrap().to_string();
+                (op_name, op["path"].as_str().unwrap().to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn only_changed_results_are_sent() {
+        let before = written(
+            &[("a", "pass"), ("b", "pass"), ("c", "pass")],
+            &["a", "b", "c"],
+        );
+        let after = results(&[("a", "pass"), ("b", "fail"), ("c", "pass")]);
+        let (operations, order) = changes(&before, &after).unwrap();
+        assert_eq!(
+            ops(&operations),
+            [
+                ("test".into(), "/metadata/resourceVersion".into()),
+                ("replace".into(), "/results/1".into()),
+                ("add".into(), "/summary".into()),
+            ]
+        );
+        assert_eq!(operations[0]["value"], "42");
+        assert_eq!(operations[1]["value"]["result"], "fail");
+        assert_eq!(
+            operations[2]["value"],
+            json!({ "pass": 2, "fail": 1, "warn": 0, "error": 0, "skip": 0 })
+        );
+        assert_eq!(uids(&order), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn removals_run_back_to_front_and_additions_append() {
+        let before = written(
+            &[("a", "pass"), ("b", "pass"), ("c", "pass"), ("d", "fail")],
+            &["d", "a", "b", "c"],
+        );
+        let after = results(&[("a", "pass"), ("c", "pass"), ("e", "fail")]);
+        let (operations, order) = changes(&before, &after).unwrap();
+        assert_eq!(
+            ops(&operations),
+            [
+                ("test".into(), "/metadata/resourceVersion".into()),
+                ("remove".into(), "/results/2".into()),
+                ("remove".into(), "/results/0".into()),
+                ("add".into(), "/results/-".into()),
+                ("add".into(), "/summary".into()),
+            ]
+        );
+        assert_eq!(uids(&order), ["a", "c", "e"]);
+
+        // Replaying the patch on the list it was computed from gives the new order.
+        let mut list: Vec<Value> = before
+            .order
+            .iter()
+            .map(|id| entry(&before.results[id]))
+            .collect();
+        let mut report = json!({ "metadata": { "resourceVersion": "42" }, "results": list });
+        let patch: json_patch::Patch = serde_json::from_value(Value::Array(operations)).unwrap();
+        json_patch::patch(&mut report, &patch).unwrap();
+        list = order.iter().map(|id| entry(&after[id])).collect();
+        assert_eq!(report["results"], Value::Array(list));
+    }
+
+    #[test]
+    fn unknown_versions_are_written_whole() {
+        let mut before = written(&[("a", "pass")], &["a"]);
+        before.resource_version = None;
+        assert!(changes(&before, &results(&[("a", "fail")])).is_none());
+    }
+}
@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::time::Duration;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+pub type TaskResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+/// Resolves on the first SIGTERM or SIGINT.
+pub async fn signal_received() -> std::io::Result<()> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = sigterm.recv() => info!("Received SIGTERM"),
+        res = tokio::signal::ctrl_c() => {
+            res?;
+            info!("Received SIGINT");
+        }
+    }
+    Ok(())
+}
+
+/// Runs `tasks` until a termination signal arrives or any task exits, then
+/// cancels `token` and gives the remaining tasks `drain_timeout` to finish.
+pub async fn run_until_shutdown(
+    mut tasks: JoinSet<TaskResult>,
+    token: CancellationToken,
+    drain_timeout: Duration,
+) -> TaskResult {
+    let mut outcome: TaskResult = Ok(());
+
+    tokio::select! {
+        res = signal_received() => res?,
+        Some(joined) = tasks.join_next() => {
+            warn!("A component stopped unexpectedly, shutting down");
+            outcome = flatten(joined);
+        }
+    }
+
+    token.cancel();
+    info!("Draining for up to {}s", drain_timeout.as_secs());
+
+    let drained = tokio::time::timeout(drain_timeout, async {
+        while let Some(joined) = tasks.join_next().await {
+            if let Err(e) = flatten(joined) {
+                error!("Component failed during shutdown: {e}");
+            }
+        }
+    })
+    .await;
+
+    if drained.is_err() {
+        warn!("Drain timeout elapsed, aborting remaining tasks");
+        tasks.abort_all();
+    }
+    outcome
+}
+
+fn flatten(joined: Result<TaskResult, tokio::task::JoinError>) -> TaskResult {
+    joined.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?
+}
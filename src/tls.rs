@@ -0,0 +1,177 @@
+use axum_server::tls_rustls::RustlsConfig;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Loads the serving certificate and keeps it current: the files are polled
+/// and reloaded in place whenever they change, so a Secret rotated by
+/// cert-manager is picked up without restarting the pod.
+pub struct ReloadingCertificate {
+    pub config: RustlsConfig,
+    cert: PathBuf,
+    key: PathBuf,
+    last_modified: (SystemTime, SystemTime),
+}
+
+impl ReloadingCertificate {
+    pub async fn load(cert: &Path, key: &Path) -> std::io::Result<Self> {
+        let last_modified = modified(cert, key)?;
+        let config = RustlsConfig::from_pem_file(cert, key).await?;
+        info!("Loaded TLS certificate from {}", cert.display());
+        Ok(ReloadingCertificate {
+            config,
+            cert: cert.to_path_buf(),
+            key: key.to_path_buf(),
+            last_modified,
+        })
+    }
+
+    /// Polls for changes every `interval` until `shutdown` is cancelled.
+    pub async fn watch(mut self, interval: Duration, shutdown: CancellationToken) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => self.reload_if_changed().await,
+                _ = shutdown.cancelled() => return,
+            }
+        }
+    }
+
+    async fn reload_if_changed(&mut self) {
+        let current = match modified(&self.cert, &self.key) {
+            Ok(current) => current,
+            Err(e) => {
+                warn!("Cannot stat TLS files: {e}");
+                return;
+            }
+        };
+        if current == self.last_modified {
+            return;
+        }
+
+        // On failure the old certificate keeps serving and the next tick retries,
+        // which covers catching the cert and key halfway through an update.
+        match self
+            .config
+            .reload_from_pem_file(&self.cert, &self.key)
+            .await
+        {
+            Ok(()) => {
+                info!("Reloaded TLS certificate from {}", self.cert.display());
+                self.last_modified = current;
+            }
+            Err(e) => warn!("Cannot reload TLS certificate, keeping the previous one: {e}"),
+        }
+    }
+}
+
+// `metadata` follows symlinks, so the atomic `..data` swap kubelet performs
+// for Secret volumes shows up as a new modification time.
+fn modified(cert: &Path, key: &Path) -> std::io::Result<(SystemTime, SystemTime)> {
+    Ok((
+        std::fs::metadata(cert)?.modified()?,
+        std::fs::metadata(key)?.modified()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::sync::Arc;
+
+    /// A directory of its own for each test, holding `tls.crt` and `tls.key`.
+    fn dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("kube-guardian-{}-tls-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes a fresh self-signed pair, stamped `age` after the epoch so
+    /// rewrites are seen as changes however coarse the file system clock is.
+    fn write(dir: &Path, cert: &str, key: &str, age: u64) {
+        let stamp = SystemTime::UNIX_EPOCH + Duration::from_secs(age);
+        for (file, contents) in [("tls.crt", cert), ("tls.key", key)] {
+            let path = dir.join(file);
+            std::fs::write(&path, contents).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(stamp)
+                .unwrap();
+        }
+    }
+
+    fn issue(dir: &Path, age: u64) {
+        let issued = rcgen::generate_simple_self_signed(vec!["webhook".to_string()]).unwrap();
+        write(
+            dir,
+            &issued.cert.pem(),
+            &issued.key_pair.serialize_pem(),
+            age,
+        );
+    }
+
+    async fn load(dir: &Path) -> ReloadingCertificate {
+        // Installed once per test binary, as `main` does for the server.
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        ReloadingCertificate::load(&dir.join("tls.crt"), &dir.join("tls.key"))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn reloads_after_the_files_change() {
+        let dir = dir("reload");
+        issue(&dir, 1_000);
+        let mut certificate = load(&dir).await;
+        let loaded = certificate.config.get_inner();
+
+        certificate.reload_if_changed().await;
+        assert!(Arc::ptr_eq(&loaded, &certificate.config.get_inner()));
+
+        issue(&dir, 2_000);
+        certificate.reload_if_changed().await;
+        assert!(!Arc::ptr_eq(&loaded, &certificate.config.get_inner()));
+        assert_eq!(
+            certificate.last_modified,
+            modified(&dir.join("tls.crt"), &dir.join("tls.key")).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn a_broken_pair_keeps_the_previous_certificate() {
+        let dir = dir("broken");
+        issue(&dir, 1_000);
+        let mut certificate = load(&dir).await;
+        let loaded = certificate.config.get_inner();
+        let last_modified = certificate.last_modified;
+
+        // The cert was replaced but the key not yet.
+        let cert = std::fs::read_to_string(dir.join("tls.crt")).unwrap();
+        write(&dir, &cert, "not a key", 2_000);
+        certificate.reload_if_changed().await;
+        assert!(Arc::ptr_eq(&loaded, &certificate.config.get_inner()));
+        assert_eq!(certificate.last_modified, last_modified);
+
+        // The next tick retries and picks up the finished update.
+        issue(&dir, 3_000);
+        certificate.reload_if_changed().await;
+        assert!(!Arc::ptr_eq(&loaded, &certificate.config.get_inner()));
+    }
+
+    #[tokio::test]
+    async fn missing_files_fail_to_load() {
+        let dir = dir("missing");
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        assert!(
+            ReloadingCertificate::load(&dir.join("tls.crt"), &dir.join("tls.key"))
+                .await
+                .is_err()
+        );
+    }
+}
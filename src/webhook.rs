@@ -1,10 +1,661 @@
-use axum::Router;
+use axum::extract::{DefaultBodyLimit, State};
 use axum::routing::post;
+use axum::{Json, Router};
+use axum_server::Handle;
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::Namespace;
+use kube::Api;
+use kube::api::DynamicObject;
+use kube::core::admission::{AdmissionRequest, AdmissionResponse, AdmissionReview, Operation};
+use kube::runtime::reflector::{self, ObjectRef, Store};
+use kube::runtime::{WatchStreamExt, watcher};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
 
-pub fn router() -> Router {
-    Router::new().route("/validate", post(validate))
+use crate::certs;
+use crate::context::Context;
+use crate::crd::{EnforcementAction, FailurePolicy, GuardianPolicy};
+use crate::governance::cel::CompiledPolicy;
+use crate::governance::policies::Target;
+use crate::governance::traits::{EvaluationContext, EvaluationResult, PolicyEvaluator};
+use crate::shutdown::TaskResult;
+use crate::tls::ReloadingCertificate;
+
+/// What the handlers read: cluster state mirrored by reflectors and the
+/// shared context holding the compiled policies.
+#[derive(Clone)]
+pub struct WebhookState {
+    pub policies: Store<GuardianPolicy>,
+    pub namespaces: Store<Namespace>,
+    pub ctx: Arc<Context>,
+}
+
+/// The largest object etcd stores by default (`--max-request-bytes`).
+const ETCD_OBJECT_LIMIT: usize = 1536 * 1024;
+
+/// An UPDATE review carries both the object and the old object, plus the
+/// request envelope. Axum's 2 MiB default would answer large ConfigMaps and
+/// Secrets with 413, failing the write or skipping policy.
+const MAX_REVIEW_BYTES: usize = 2 * ETCD_OBJECT_LIMIT + 512 * 1024;
+
+pub fn router(state: WebhookState) -> Router {
+    Router::new()
+        .route("/validate", post(validate))
+        .route("/mutate", post(mutate))
+        .layer(DefaultBodyLimit::max(MAX_REVIEW_BYTES))
+        .with_state(state)
+}
+
+/// Serves the webhook until `shutdown` is cancelled, then stops accepting
+/// connections and waits for open requests to complete.
+pub async fn serve(ctx: Arc<Context>, shutdown: CancellationToken) -> TaskResult {
+    let config = &ctx.config.webhook;
+    let client = ctx.client.clone();
+    let cache = ctx.policies.clone();
+    let policies = mirror_with(
+        Api::<GuardianPolicy>::all(client.clone()),
+        &shutdown,
+        move |event, store| cache.prune(event, store),
+    );
+    let namespaces = mirror(Api::<Namespace>::all(client.clone()), &shutdown);
+
+    // Answering before the policy cache is populated would admit everything.
+    tokio::select! {
+        res = async {
+            policies.wait_until_ready().await?;
+            namespaces.wait_until_ready().await
+        } => res?,
+        _ = shutdown.cancelled() => return Ok(()),
+    }
+
+    if config.bootstrap.enabled {
+        let namespaces = &ctx.config.watch_namespaces;
+        certs::ensure(&client, &config.bootstrap, &config.tls, namespaces).await?;
+        tokio::spawn(certs::maintain(
+            client.clone(),
+            config.bootstrap.clone(),
+            config.tls.clone(),
+            namespaces.clone(),
+            shutdown.clone(),
+        ));
+    }
+
+    let app = router(WebhookState {
+        policies,
+        namespaces,
+        ctx: ctx.clone(),
+    });
+    let addr = config.bind_address;
+    let (Some(cert), Some(key)) = (&config.tls.cert_path, &config.tls.key_path) else {
+        warn!(
+            "No TLS certificate configured; serving plain HTTP, which the API server will not call"
+        );
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        info!("Webhook listening on http://{addr}");
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await?;
+        info!("Webhook stopped");
+        return Ok(());
+    };
+
+    let certificate = ReloadingCertificate::load(cert, key).await?;
+    let tls = certificate.config.clone();
+    tokio::spawn(certificate.watch(config.tls.reload_interval(), shutdown.clone()));
+
+    let handle = Handle::new();
+    let stop = handle.clone();
+    tokio::spawn(async move {
+        shutdown.cancelled().await;
+        // The supervisor enforces the drain timeout, so wait for open requests here.
+        stop.graceful_shutdown(None);
+    });
+
+    info!("Webhook listening on https://{addr}");
+    axum_server::bind_rustls(addr, tls)
+        .handle(handle)
+        .serve(app.into_make_service())
+        .await?;
+    info!("Webhook stopped");
+    Ok(())
+}
+
+/// Keeps a reflector store of `api` up to date until `shutdown` is cancelled.
+pub fn mirror<K>(api: Api<K>, shutdown: &CancellationToken) -> Store<K>
+where
+    K: kube::Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug + Send + Sync + 'static,
+{
+    mirror_with(api, shutdown, |_, _| {})
+}
+
+/// Like [`mirror`], calling `observe` with every watch event once the store
+/// reflects it.
+pub fn mirror_with<K>(
+    api: Api<K>,
+    shutdown: &CancellationToken,
+    observe: impl Fn(&watcher::Event<K>, &Store<K>) + Send + 'static,
+) -> Store<K>
+where
+    K: kube::Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug + Send + Sync + 'static,
+{
+    let (store, writer) = reflector::store();
+    let reader = store.clone();
+    let watch = watcher(api, watcher::Config::default())
+        .default_backoff()
+        .reflect(writer)
+        .for_each(move |event| {
+            if let Ok(event) = event {
+                observe(&event, &reader);
+            }
+            futures::future::ready(())
+        });
+    let token = shutdown.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = watch => {}
+            _ = token.cancelled() => {}
+        }
+    });
+    store
+}
+
+async fn validate(
+    State(state): State<WebhookState>,
+    Json(review): Json<AdmissionReview<DynamicObject>>,
+) -> Json<AdmissionReview<DynamicObject>> {
+    let started = Instant::now();
+    let request: AdmissionRequest<DynamicObject> = match review.try_into() {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("Malformed AdmissionReview: {e}");
+            state
+                .ctx
+                .metrics
+                .admission("validate", None, "invalid", started);
+            return Json(AdmissionResponse::invalid(e.to_string()).into_review());
+        }
+    };
+
+    let policies = active_policies(&state);
+    let results = review_request(&state, &policies, &request);
+
+    let mut response = AdmissionResponse::from(&request);
+    let subject = format!(
+        "{:?} {}/{}",
+        request.operation, request.kind.kind, request.name
+    );
+    let mut denials = Vec::new();
+    let mut warnings = Vec::new();
+    for result in &results {
+        let failures = result.failures().map(ToString::to_string);
+        match result.enforcement_action {
+            EnforcementAction::Deny => denials.extend(failures),
+            EnforcementAction::Warn => {
+                warnings.extend(failures.map(|failure| format!("kube-guardian: {failure}")));
+            }
+            // Audit policies only show up in reports, which the background
+            // scanner writes; the client is told nothing.
+            EnforcementAction::Audit => {
+                for error in result.errors() {
+                    debug!("Audit: could not evaluate {error} for {subject}");
+                }
+                continue;
+            }
+            EnforcementAction::DryRun => {
+                for failure in failures {
+                    info!("Dry run: {subject} violates {failure}");
+                }
+                continue;
+            }
+        }
+        let errors: Vec<String> = result.errors().map(ToString::to_string).collect();
+        if errors.is_empty() {
+            continue;
+        }
+        warn!(
+            "Could not evaluate {} rule(s) for {subject}: {}",
+            errors.len(),
+            errors.join("; ")
+        );
+        // A deny policy that fails closed treats an error like a violation;
+        // otherwise the client is only told about it.
+        if result.enforcement_action == EnforcementAction::Deny
+            && result.failure_policy == FailurePolicy::Fail
+        {
+            denials.extend(errors);
+        } else {
+            warnings.extend(
+                errors
+                    .into_iter()
+                    .map(|error| format!("kube-guardian: {error}")),
+            );
+        }
+    }
+    if !warnings.is_empty() {
+        response.warnings = Some(warnings);
+    }
+
+    let response = if denials.is_empty() {
+        state
+            .ctx
+            .metrics
+            .admission("validate", Some(&request), "allowed", started);
+        response
+    } else {
+        let message = format!("kube-guardian: {}", denials.join("; "));
+        info!("Denied {subject}: {message}");
+        state
+            .ctx
+            .metrics
+            .admission("validate", Some(&request), "denied", started);
+        response.deny(message)
+    };
+    Json(response.into_review())
+}
+
+async fn mutate(
+    State(state): State<WebhookState>,
+    Json(review): Json<AdmissionReview<DynamicObject>>,
+) -> Json<AdmissionReview<DynamicObject>> {
+    let started = Instant::now();
+    let request: AdmissionRequest<DynamicObject> = match review.try_into() {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("Malformed AdmissionReview: {e}");
+            state
+                .ctx
+                .metrics
+                .admission("mutate", None, "invalid", started);
+            return Json(AdmissionResponse::invalid(e.to_string()).into_review());
+        }
+    };
+
+    let response = AdmissionResponse::from(&request);
+    let (response, decision) = match apply_defaults(&state, &request) {
+        Some(patch) => match response.clone().with_patch(patch) {
+            Ok(patched) => (patched, "patched"),
+            Err(e) => {
+                warn!("Cannot serialize JSON patch, admitting unchanged: {e}");
+                (response, "allowed")
+            }
+        },
+        None => (response, "allowed"),
+    };
+    state
+        .ctx
+        .metrics
+        .admission("mutate", Some(&request), decision, started);
+    Json(response.into_review())
+}
+
+/// The patch that applies every selected rule's defaults, if any change.
+fn apply_defaults(
+    state: &WebhookState,
+    request: &AdmissionRequest<DynamicObject>,
+) -> Option<json_patch::Patch> {
+    let (target, original) = admitted_object(state, request)?;
+    let mut mutated = original.clone();
+    let applied: Vec<String> = active_policies(state)
+        .iter()
+        .map(|compiled| &compiled.policy)
+        // Only policies that act at admission change admitted objects.
+        .filter(|policy| {
+            matches!(
+                policy.enforcement_action,
+                EnforcementAction::Deny | EnforcementAction::Warn
+            )
+        })
+        .flat_map(|policy| {
+            policy
+                .apply_defaults(&target, &mut mutated)
+                .into_iter()
+                .map(move |rule| format!("{}/{rule}", policy.name))
+        })
+        .collect();
+    if applied.is_empty() {
+        return None;
+    }
+
+    info!(
+        "Defaulting {:?} {}/{} from {}",
+        request.operation,
+        request.kind.kind,
+        request.name,
+        applied.join(", ")
+    );
+    Some(json_patch::diff(&original, &mutated))
 }
 
-async fn validate() {
-    println!("Validation request received");
+/// Enabled policies the reconciler would mark Ready; invalid ones are not
+/// enforced until fixed.
+fn active_policies(state: &WebhookState) -> Vec<Arc<CompiledPolicy>> {
+    let mut policies = state.ctx.policies.compile_all(&state.policies.state());
+    policies.retain(|compiled| compiled.policy.enabled && compiled.is_valid());
+    policies
+}
+
+/// The object being written by a CREATE or UPDATE in a governed namespace,
+/// with the match target taken from the request so it reflects the resource
+/// actually admitted.
+fn admitted_object(
+    state: &WebhookState,
+    request: &AdmissionRequest<DynamicObject>,
+) -> Option<(Target, Value)> {
+    if !state.ctx.config.governs(request.namespace.as_deref()) {
+        return None;
+    }
+    // Deletes and connects carry no new object state to check.
+    let object = match (&request.operation, &request.object) {
+        (Operation::Create | Operation::Update, Some(object)) => object,
+        _ => return None,
+    };
+    let object = match serde_json::to_value(object) {
+        Ok(object) => object,
+        Err(e) => {
+            warn!("Cannot serialize admitted object: {e}");
+            return None;
+        }
+    };
+
+    let mut target = Target::from_object(&object);
+    target.api_group = request.kind.group.clone();
+    target.kind = request.kind.kind.clone();
+    if request.namespace.is_some() {
+        target.namespace = request.namespace.clone();
+    }
+    Some((target, object))
+}
+
+fn review_request(
+    state: &WebhookState,
+    policies: &[Arc<CompiledPolicy>],
+    request: &AdmissionRequest<DynamicObject>,
+) -> Vec<EvaluationResult> {
+    let Some((target, object)) = admitted_object(state, request) else {
+        return Vec::new();
+    };
+    let old_object = request
+        .old_object
+        .as_ref()
+        .and_then(|old| serde_json::to_value(old).ok());
+    let request_value = serde_json::to_value(request).ok();
+    let namespace_object = target.namespace.as_ref().and_then(|ns| {
+        let namespace = state.namespaces.get(&ObjectRef::new(ns))?;
+        serde_json::to_value(namespace.as_ref()).ok()
+    });
+    let context = EvaluationContext {
+        target: &target,
+        old_object: old_object.as_ref(),
+        request: request_value.as_ref(),
+        namespace_object: namespace_object.as_ref(),
+    };
+    policies
+        .iter()
+        .map(|policy| policy.evaluate(&object, &context))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use axum::body::{Body, to_bytes};
+    use axum::http::{Request, StatusCode, header};
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    fn state() -> WebhookState {
+        state_with(Vec::new())
+    }
+
+    fn state_with(policies: Vec<Value>) -> WebhookState {
+        let config = kube::Config::new("http://127.0.0.1:9".parse().unwrap());
+        let client = kube::Client::try_from(config).unwrap();
+        let (store, mut writer) = reflector::store();
+        for policy in policies {
+            let policy: GuardianPolicy = serde_json::from_value(policy).unwrap();
+            writer.apply_watcher_event(&watcher::Event::Apply(policy));
+        }
+        WebhookState {
+            policies: store,
+            namespaces: reflector::store().0,
+            ctx: Context::new(client, Config::default()),
+        }
+    }
+
+    fn require_team(condition: &str, failure_policy: Option<&str>) -> Value {
+        let mut policy = json!({
+            "apiVersion": "guardian.io/v1",
+            "kind": "GuardianPolicy",
+            "metadata": { "name": "require-labels", "uid": "1", "generation": 1 },
+            "spec": {
+                "severity": "High",
+                "rules": [{
+                    "name": "team-label",
+                    "match": { "kinds": ["Deployment"] },
+                    "condition": condition,
+                    "message": "workloads must carry a team label",
+                }],
+            },
+        });
+        if let Some(failure_policy) = failure_policy {
+            policy["spec"]["failurePolicy"] = json!(failure_policy);
+        }
+        policy
+    }
+
+    fn create(object: Value) -> Value {
+        json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": {
+                "uid": "e911857d-c318-11e8-bbad-025000000001",
+                "kind": { "group": "apps", "version": "v1", "kind": "Deployment" },
+                "resource": { "group": "apps", "version": "v1", "resource": "deployments" },
+                "name": "web",
+                "namespace": "default",
+                "operation": "CREATE",
+                "userInfo": { "username": "admin" },
+                "object": object,
+                "dryRun": false,
+            },
+        })
+    }
+
+    fn unlabeled_deployment() -> Value {
+        json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": { "name": "web", "namespace": "default" },
+        })
+    }
+
+    async fn review(state: WebhookState, review: &Value) -> Value {
+        post(state, "/validate", review).await
+    }
+
+    async fn post(state: WebhookState, path: &str, review: &Value) -> Value {
+        let request = Request::post(path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(review).unwrap()))
+            .unwrap();
+        let response = router(state).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<Value>(&body).unwrap()["response"].clone()
+    }
+
+    #[tokio::test]
+    async fn deny_policies_fail_closed_on_evaluation_errors() {
+        let state = state_with(vec![require_team("has(object.metadata.labels.team)", None)]);
+        let response = review(state, &create(unlabeled_deployment())).await;
+        assert_eq!(response["allowed"], json!(false));
+        let message = response["status"]["message"].as_str().unwrap();
+        assert!(
+            message.contains("condition failed to evaluate"),
+            "{message}"
+        );
+    }
+
+    #[tokio::test]
+    async fn ignored_evaluation_errors_only_warn() {
+        let state = state_with(vec![require_team(
+            "has(object.metadata.labels.team)",
+            Some("Ignore"),
+        )]);
+        let response = review(state, &create(unlabeled_deployment())).await;
+        assert_eq!(response["allowed"], json!(true));
+        assert_eq!(response["warnings"].as_array().map(Vec::len), Some(1));
+    }
+
+    #[tokio::test]
+    async fn audit_policies_are_silent_at_admission() {
+        for condition in [
+            "has(object.metadata.labels.team)",
+            "has(object.metadata.labels) && 'team' in object.metadata.labels",
+        ] {
+            let mut policy = require_team(condition, None);
+            policy["spec"]["enforcementAction"] = json!("audit");
+            let response = review(state_with(vec![policy]), &create(unlabeled_deployment())).await;
+            assert_eq!(response["allowed"], json!(true));
+            assert!(
+                response.get("warnings").is_none(),
+                "{condition}: {response}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_policies_are_not_enforced() {
+        let mut policy = require_team("has(object.metadata.labels)", None);
+        // Marked InvalidSpec by the reconciler: one check per pattern.
+        policy["spec"]["rules"][0]["patterns"] =
+            json!([{ "path": "metadata.labels.team", "exists": true, "absent": true }]);
+        let response = review(state_with(vec![policy]), &create(unlabeled_deployment())).await;
+        assert_eq!(response["allowed"], json!(true));
+        assert!(response.get("warnings").is_none());
+    }
+
+    #[tokio::test]
+    async fn guarded_condition_denies_unlabeled_workloads() {
+        let condition = "has(object.metadata.labels) && 'team' in object.metadata.labels";
+        let state = state_with(vec![require_team(condition, Some("Ignore"))]);
+        let response = review(state.clone(), &create(unlabeled_deployment())).await;
+        assert_eq!(response["allowed"], json!(false));
+        let message = response["status"]["message"].as_str().unwrap();
+        assert!(
+            message.contains("workloads must carry a team label"),
+            "{message}"
+        );
+
+        let mut labeled = unlabeled_deployment();
+        labeled["metadata"]["labels"] = json!({ "team": "payments" });
+        let response = review(state, &create(labeled)).await;
+        assert_eq!(response["allowed"], json!(true));
+    }
+
+    fn config_map(data: &str) -> Value {
+        json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "large", "namespace": "default" },
+            "data": { "blob": data },
+        })
+    }
+
+    #[tokio::test]
+    async fn accepts_updates_of_objects_near_the_etcd_limit() {
+        let data = "x".repeat(ETCD_OBJECT_LIMIT - 1024);
+        let update = json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": {
+                "uid": "e911857d-c318-11e8-bbad-025000000001",
+                "kind": { "group": "", "version": "v1", "kind": "ConfigMap" },
+                "resource": { "group": "", "version": "v1", "resource": "configmaps" },
+                "name": "large",
+                "namespace": "default",
+                "operation": "UPDATE",
+                "userInfo": { "username": "admin" },
+                "object": config_map(&data),
+                "oldObject": config_map(&data),
+                "dryRun": false,
+            },
+        });
+        assert!(serde_json::to_vec(&update).unwrap().len() > 2 * 1024 * 1024);
+
+        let response = review(state(), &update).await;
+        assert_eq!(response["allowed"], json!(true));
+    }
+
+    fn platform_defaults(action: &str) -> Value {
+        json!({
+            "apiVersion": "guardian.io/v1",
+            "kind": "GuardianPolicy",
+            "metadata": { "name": "platform", "uid": "2", "generation": 1 },
+            "spec": {
+                "severity": "Low",
+                "enforcementAction": action,
+                "rules": [{
+                    "name": "defaults",
+                    "match": { "kinds": ["Deployment"] },
+                    "defaults": {
+                        "labels": { "owner": "platform" },
+                        "resourceRequests": { "cpu": "100m" },
+                    },
+                }],
+            },
+        })
+    }
+
+    fn patch(response: &Value) -> Value {
+        assert_eq!(response["patchType"], "JSONPatch");
+        let patch: Vec<u8> = serde_json::from_value(response["patch"].clone()).unwrap();
+        serde_json::from_slice(&patch).unwrap()
+    }
+
+    #[tokio::test]
+    async fn mutate_answers_with_a_json_patch() {
+        let mut deployment = unlabeled_deployment();
+        deployment["metadata"]["labels"] = json!({ "app": "web" });
+        deployment["spec"] = json!({ "template": { "spec": { "containers": [
+            { "name": "app", "resources": {} },
+        ]}}});
+        let state = state_with(vec![platform_defaults("deny")]);
+        let response = post(state, "/mutate", &create(deployment)).await;
+        assert_eq!(response["allowed"], json!(true));
+        assert_eq!(
+            patch(&response),
+            json!([
+                { "op": "add", "path": "/metadata/labels/owner", "value": "platform" },
+                {
+                    "op": "add",
+                    "path": "/spec/template/spec/containers/0/resources/requests",
+                    "value": { "cpu": "100m" },
+                },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn mutate_leaves_defaulted_objects_alone() {
+        let mut deployment = unlabeled_deployment();
+        deployment["metadata"]["labels"] = json!({ "owner": "payments" });
+        let state = state_with(vec![platform_defaults("deny")]);
+        let response = post(state, "/mutate", &create(deployment.clone())).await;
+        assert_eq!(response["allowed"], json!(true));
+        assert!(response.get("patch").is_none());
+
+        // Audit and dry-run policies never change admitted objects.
+        for action in ["audit", "dryrun"] {
+            let state = state_with(vec![platform_defaults(action)]);
+            let response = post(state, "/mutate", &create(unlabeled_deployment())).await;
+            assert!(response.get("patch").is_none(), "{action}");
+        }
+    }
 }